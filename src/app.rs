@@ -1,4 +1,6 @@
-use crate::{config, controller::Controller, utils};
+use crate::control::ControlServer;
+use crate::scheduler::{Scheduler, TickPolicy, TickTimer, Waker};
+use crate::{config, controller::Controller, protocol, utils};
 use objc2::rc::{Allocated, Retained};
 use objc2::runtime::{AnyObject, ProtocolObject};
 use objc2::{
@@ -9,14 +11,18 @@ use objc2_app_kit::{
     NSMenuItem, NSStatusBar, NSStatusBarButton, NSStatusItem,
 };
 use objc2_foundation::{NSNotification, NSObject, NSObjectProtocol, NSString, NSTimer};
-use std::cell::RefCell;
+use std::cell::{Cell, RefCell};
 
 #[derive(Debug)]
 struct AppDelegateIvars {
     controller: RefCell<Controller>,
     status_item: RefCell<Option<Retained<NSStatusItem>>>,
     menu: RefCell<Option<Retained<NSMenu>>>,
+    pause_item: RefCell<Option<Retained<NSMenuItem>>>,
+    shows_paused: Cell<bool>,
     timer: RefCell<Option<Retained<NSTimer>>>,
+    control: RefCell<Option<ControlServer>>,
+    scheduler: RefCell<Option<Scheduler>>,
 }
 
 define_class!(
@@ -33,7 +39,11 @@ define_class!(
                 controller: RefCell::new(Controller::new()),
                 status_item: RefCell::new(None),
                 menu: RefCell::new(None),
+                pause_item: RefCell::new(None),
+                shows_paused: Cell::new(false),
                 timer: RefCell::new(None),
+                control: RefCell::new(None),
+                scheduler: RefCell::new(None),
             });
             unsafe { msg_send![super(this), init] }
         }
@@ -41,7 +51,35 @@ define_class!(
         #[unsafe(method(tick:))]
         fn tick(&self, _timer: &NSTimer) {
             utils::disable_local_event_suppression();
+            self.handle_control_requests();
             self.controller().borrow_mut().update_state();
+            self.refresh_pause_item();
+            let busy = self.controller().borrow().is_busy();
+            if let Some(scheduler) = self.scheduler().borrow_mut().as_mut() {
+                let policy = TickPolicy::from_config(&config());
+                scheduler.after_tick(&mut DelegateTimer(self), &policy, busy, now());
+            }
+        }
+
+        // Sent by the waker from the input or control thread while ticks are stopped
+        #[unsafe(method(wake:))]
+        fn wake(&self, _sender: Option<&AnyObject>) {
+            let woke = match self.scheduler().borrow_mut().as_mut() {
+                Some(scheduler) => {
+                    let policy = TickPolicy::from_config(&config());
+                    scheduler.wake(&mut DelegateTimer(self), &policy, now())
+                }
+                None => false,
+            };
+            if woke {
+                self.controller().borrow_mut().resume_from_idle();
+            }
+        }
+
+        #[unsafe(method(togglePause:))]
+        fn toggle_pause(&self, _sender: &NSMenuItem) {
+            self.controller().borrow_mut().toggle_paused();
+            self.refresh_pause_item();
         }
     }
 
@@ -52,18 +90,29 @@ define_class!(
         fn did_finish_launching(&self, _notification: &NSNotification) {
             self.controller().borrow_mut().start();
             let mtm = MainThreadMarker::new().expect("must be on the main thread");
-            let (status_item, menu) = build_status_item(mtm);
+            let (status_item, menu, pause_item) = build_status_item(mtm, self);
             *self.status_item().borrow_mut() = Some(status_item);
             *self.menu().borrow_mut() = Some(menu);
-            let timer = schedule_timer(self);
-            *self.timer().borrow_mut() = Some(timer);
+            *self.pause_item().borrow_mut() = Some(pause_item);
+            let delegate = DelegatePtr(self);
+            let waker = Waker::new(move || delegate.wake());
+            self.controller().borrow().set_waker(waker.clone());
+            *self.scheduler().borrow_mut() = Some(Scheduler::new(waker.clone()));
+            let policy = TickPolicy::from_config(&config());
+            if let Some(scheduler) = self.scheduler().borrow_mut().as_mut() {
+                scheduler.wake(&mut DelegateTimer(self), &policy, now());
+            }
+            match ControlServer::bind_with_waker(&protocol::socket_path(), Some(waker)) {
+                Ok(server) => *self.control().borrow_mut() = Some(server),
+                Err(error) => log::warn!("control socket unavailable: {}", error),
+            }
         }
 
         #[unsafe(method(applicationWillTerminate:))]
         fn will_terminate(&self, _notification: &NSNotification) {
-            if let Some(timer) = self.timer().borrow_mut().take() {
-                timer.invalidate();
-            }
+            self.scheduler().borrow_mut().take();
+            DelegateTimer(self).stop();
+            self.control().borrow_mut().take();
             self.controller().borrow_mut().stop();
         }
     }
@@ -82,12 +131,92 @@ impl AppDelegate {
         &self.ivars().menu
     }
 
+    fn pause_item(&self) -> &RefCell<Option<Retained<NSMenuItem>>> {
+        &self.ivars().pause_item
+    }
+
     fn timer(&self) -> &RefCell<Option<Retained<NSTimer>>> {
         &self.ivars().timer
     }
+
+    // Pausing can also come from the control socket, so the title follows the controller
+    fn refresh_pause_item(&self) {
+        let paused = self.controller().borrow().is_paused();
+        if paused == self.ivars().shows_paused.get() {
+            return;
+        }
+        self.ivars().shows_paused.set(paused);
+        if let Some(item) = self.pause_item().borrow().as_ref() {
+            item.setTitle(&NSString::from_str(pause_title(paused)));
+        }
+    }
+
+    fn control(&self) -> &RefCell<Option<ControlServer>> {
+        &self.ivars().control
+    }
+
+    fn scheduler(&self) -> &RefCell<Option<Scheduler>> {
+        &self.ivars().scheduler
+    }
+
+    fn handle_control_requests(&self) {
+        let control = self.control().borrow();
+        let Some(server) = control.as_ref() else {
+            return;
+        };
+        while let Some(request) = server.try_recv() {
+            let reply = self.controller().borrow_mut().execute(&request.command);
+            request.reply(reply);
+        }
+    }
 }
 
-fn build_status_item(mtm: MainThreadMarker) -> (Retained<NSStatusItem>, Retained<NSMenu>) {
+// The delegate lives until the process exits, and the waker only uses it to message the main thread
+struct DelegatePtr(*const AppDelegate);
+
+unsafe impl Send for DelegatePtr {}
+unsafe impl Sync for DelegatePtr {}
+
+impl DelegatePtr {
+    fn wake(&self) {
+        unsafe {
+            let _: () = msg_send![
+                &*self.0,
+                performSelectorOnMainThread: sel!(wake:),
+                withObject: Option::<&AnyObject>::None,
+                waitUntilDone: false
+            ];
+        }
+    }
+}
+
+struct DelegateTimer<'a>(&'a AppDelegate);
+
+impl TickTimer for DelegateTimer<'_> {
+    fn start(&mut self, interval: f64) {
+        self.stop();
+        *self.0.timer().borrow_mut() = Some(schedule_timer(self.0, interval));
+    }
+
+    fn stop(&mut self) {
+        if let Some(timer) = self.0.timer().borrow_mut().take() {
+            timer.invalidate();
+        }
+    }
+}
+
+fn now() -> f64 {
+    objc2_core_foundation::CFAbsoluteTimeGetCurrent()
+}
+
+fn pause_title(paused: bool) -> &'static str {
+    if paused { "Resume Lapsus" } else { "Pause Lapsus" }
+}
+
+fn build_status_item(
+    mtm: MainThreadMarker,
+    delegate: &AppDelegate,
+) -> (Retained<NSStatusItem>, Retained<NSMenu>, Retained<NSMenuItem>) {
     let status_bar = NSStatusBar::systemStatusBar();
     let status_item = status_bar.statusItemWithLength(-1.0);
     let button: Option<Retained<NSStatusBarButton>> = status_item.button(mtm);
@@ -97,6 +226,19 @@ fn build_status_item(mtm: MainThreadMarker) -> (Retained<NSStatusItem>, Retained
     }
     let menu_title = NSString::from_str("");
     let menu = NSMenu::initWithTitle(NSMenu::alloc(mtm), &menu_title);
+    let pause_label = NSString::from_str(pause_title(false));
+    let pause_key = NSString::from_str("p");
+    let pause_item: Retained<NSMenuItem> = unsafe {
+        NSMenuItem::initWithTitle_action_keyEquivalent(
+            NSMenuItem::alloc(mtm),
+            &pause_label,
+            Some(sel!(togglePause:)),
+            &pause_key,
+        )
+    };
+    unsafe { pause_item.setTarget(Some(delegate)) };
+    menu.addItem(&pause_item);
+    menu.addItem(&NSMenuItem::separatorItem(mtm));
     let quit_title = NSString::from_str("Quit Lapsus");
     let quit_key = NSString::from_str("q");
     let quit_item: Retained<NSMenuItem> = unsafe {
@@ -111,14 +253,14 @@ fn build_status_item(mtm: MainThreadMarker) -> (Retained<NSStatusItem>, Retained
     unsafe { quit_item.setTarget(Some(&*app)) };
     menu.addItem(&quit_item);
     status_item.setMenu(Some(&menu));
-    (status_item, menu)
+    (status_item, menu, pause_item)
 }
 
-fn schedule_timer(target: &AppDelegate) -> Retained<NSTimer> {
+fn schedule_timer(target: &AppDelegate, interval: f64) -> Retained<NSTimer> {
     unsafe {
         msg_send![
             class!(NSTimer),
-            scheduledTimerWithTimeInterval: config().min_dt,
+            scheduledTimerWithTimeInterval: interval,
             target: target,
             selector: sel!(tick:),
             userInfo: Option::<&AnyObject>::None,
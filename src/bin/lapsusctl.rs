@@ -0,0 +1,76 @@
+// Command line client for a running Lapsus, talks to it over the control socket
+
+use lapsus::protocol::{self, Command, Response};
+use std::process::ExitCode;
+
+const USAGE: &str = "usage: lapsusctl [--json] [--socket <path>] <command>
+
+commands:
+    status                 show engine and controller state
+    pause                  leave the cursor to the OS
+    resume                 start gliding again
+    profile <name>         switch to a built-in or user profile
+    get <key>              print a config value
+    set <key> <value>      change a config value
+    record start <path>    start writing a touch trace
+    record stop            finish the current trace
+    replay <trace>         feed a recorded trace into the running instance
+    calibrate start        record flicks, each followed by one touch correcting the cursor
+    calibrate save <name>  fit glide settings to the flicks and save them as profile <name>
+    calibrate cancel       stop calibrating without saving";
+
+fn main() -> ExitCode {
+    let mut json = false;
+    let mut socket = protocol::socket_path();
+    let mut args = Vec::new();
+    let mut arguments = std::env::args().skip(1);
+    while let Some(argument) = arguments.next() {
+        match argument.as_str() {
+            "--json" => json = true,
+            "--socket" => match arguments.next() {
+                Some(path) => socket = path.into(),
+                None => return usage_error("--socket needs a path"),
+            },
+            "-h" | "--help" => {
+                println!("{}", USAGE);
+                return ExitCode::SUCCESS;
+            }
+            _ => args.push(argument),
+        }
+    }
+
+    let command = match Command::from_args(&args) {
+        Ok(command) => command,
+        Err(error) => return usage_error(&error),
+    };
+    let response = match protocol::request(&socket, &command) {
+        Ok(response) => response,
+        Err(error) => Response::error(format!(
+            "cannot reach lapsus at {}: {}",
+            socket.display(),
+            error
+        )),
+    };
+
+    if json {
+        println!("{}", response.to_json());
+    } else {
+        match &response {
+            Response::Ok(fields) => {
+                for (key, value) in fields {
+                    println!("{}: {}", key, value);
+                }
+            }
+            Response::Error(message) => eprintln!("lapsusctl: {}", message),
+        }
+    }
+    match response {
+        Response::Ok(_) => ExitCode::SUCCESS,
+        Response::Error(_) => ExitCode::FAILURE,
+    }
+}
+
+fn usage_error(message: &str) -> ExitCode {
+    eprintln!("lapsusctl: {}\n\n{}", message, USAGE);
+    ExitCode::from(2)
+}
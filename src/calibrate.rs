@@ -0,0 +1,377 @@
+// Fits glide parameters to recorded flicks. A trial is a flick toward something on screen followed
+// by one touch that corrects the cursor onto it, so the correction tells how far the glide over- or
+// undershot. Fitting replays the flicks through a headless engine with candidate values and keeps
+// the ones that land closest to the corrected targets. The replay has no pointer, so it only
+// matches the live glides with the settings `simulation::FIXED_SETTINGS` runs with, and without
+// physical velocity. A session switches the app to those for as long as it runs
+
+use crate::Config;
+use crate::frame::ContactFrame;
+use crate::geometry::{Float, Point, Rect, Size, Vector};
+use crate::profile::Profile;
+use crate::simulation;
+use std::fmt::Write as _;
+use std::fs::{self, File};
+use std::io::{self, BufRead, BufReader};
+use std::path::Path;
+
+const HEADER: &str = "# lapsus calibration v1";
+
+struct Parameter {
+    key: &'static str,
+    min: Float,
+    max: Float,
+}
+
+const PARAMETERS: &[Parameter] = &[
+    Parameter {
+        key: "trackpad_velocity_gain",
+        min: 0.2,
+        max: 4.0,
+    },
+    Parameter {
+        key: "glide_decay_per_second",
+        min: 1.0,
+        max: 25.0,
+    },
+    Parameter {
+        key: "minimum_glide_velocity",
+        min: 20.0,
+        max: 1500.0,
+    },
+];
+
+// Every evaluation replays all trials, this keeps a fit well under a second
+const MAX_EVALUATIONS: usize = 400;
+// Gain and decay trade off against each other, so moving away from the current values costs this
+// many pixels per squared log step and the fit stays as close to them as the trials allow
+const REGULARIZATION: Float = 2.0;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Trial {
+    // Desktop during the trial
+    pub bounds: Rect,
+    // Cursor at lift-off
+    pub release: Point,
+    // Where the correction left the cursor
+    pub target: Point,
+    // The flick, up to and including its lift-off
+    pub frames: Vec<ContactFrame>,
+}
+
+impl Trial {
+    // Signed distance past the target along the flick, negative when the glide fell short. When
+    // the flick should not have moved the cursor at all, any glide overshoots
+    pub fn miss(&self, end: Point) -> Float {
+        let wanted = Vector {
+            dx: self.target.x - self.release.x,
+            dy: self.target.y - self.release.y,
+        };
+        let length = (wanted.dx * wanted.dx + wanted.dy * wanted.dy).sqrt();
+        if length < 1.0 {
+            return ((end.x - self.release.x).powi(2) + (end.y - self.release.y).powi(2)).sqrt();
+        }
+        ((end.x - self.target.x) * wanted.dx + (end.y - self.target.y) * wanted.dy) / length
+    }
+}
+
+// Where the cursor comes to rest when `config` glides the flick of `trial`
+pub fn simulate(trial: &Trial, config: &Config) -> Point {
+    simulation::run(&trial.frames, config, trial.bounds, trial.release, None).position
+}
+
+// Settings the live app runs with while calibrating, so the flicks glide the way their replays do.
+// They only last for the session and stay out of the saved profile
+pub fn session_settings() -> Profile {
+    simulation::FIXED_SETTINGS
+        .iter()
+        .fold(Profile::new("calibrate"), |profile, (key, value)| {
+            profile.with(key, value)
+        })
+        .with("physical_velocity", "false")
+}
+
+// Trials keep no trackpad size, so physical velocity cannot be replayed either
+pub fn check_supported(config: &Config) -> Result<(), String> {
+    simulation::check_supported(config)?;
+    if config.physical_velocity {
+        return Err("trials are replayed without physical_velocity".to_string());
+    }
+    Ok(())
+}
+
+// Root mean square of the misses, in pixels
+pub fn miss_error(trials: &[Trial], config: &Config) -> Float {
+    if trials.is_empty() {
+        return 0.0;
+    }
+    let sum: Float = trials
+        .iter()
+        .map(|trial| trial.miss(simulate(trial, config)).powi(2))
+        .sum();
+    (sum / trials.len() as Float).sqrt()
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Fit {
+    pub values: Vec<(&'static str, Float)>,
+    // Miss error with the starting config and with the fitted values
+    pub error_before: Float,
+    pub error_after: Float,
+}
+
+impl Fit {
+    // Only the fitted values, the session settings were there to make the flicks replayable
+    pub fn profile(&self, name: &str) -> Profile {
+        self.values
+            .iter()
+            .fold(Profile::new(name), |profile, (key, value)| {
+                profile.with(key, &format!("{:.3}", value))
+            })
+    }
+
+    pub fn apply(&self, base: &Config) -> Config {
+        let mut config = base.clone();
+        for (key, value) in &self.values {
+            config
+                .set(key, &value.to_string())
+                .expect("fitted keys are numeric config keys");
+        }
+        config
+    }
+}
+
+// Pattern search from the values in `base`: tries each parameter a step up and down, keeps any
+// improvement and narrows the step when nothing helps
+pub fn fit(trials: &[Trial], base: &Config) -> Result<Fit, String> {
+    if trials.is_empty() {
+        return Err("no trials to fit".to_string());
+    }
+    check_supported(base)?;
+    let start: Vec<Float> = PARAMETERS
+        .iter()
+        .map(|parameter| {
+            base.get(parameter.key)
+                .and_then(|value| value.parse::<Float>().ok())
+                .expect("fitted keys are numeric config keys")
+                .clamp(parameter.min, parameter.max)
+        })
+        .collect();
+    let candidate = |values: &[Float]| Fit {
+        values: PARAMETERS
+            .iter()
+            .zip(values)
+            .map(|(parameter, value)| (parameter.key, *value))
+            .collect(),
+        error_before: 0.0,
+        error_after: 0.0,
+    };
+    let cost = |values: &[Float]| {
+        let drift: Float = values
+            .iter()
+            .zip(&start)
+            .map(|(value, start)| (value / start).ln().powi(2))
+            .sum();
+        miss_error(trials, &candidate(values).apply(base)) + REGULARIZATION * drift
+    };
+
+    let mut best = start.clone();
+    let mut best_cost = cost(&best);
+    let mut evaluations = 1;
+    let mut factor: Float = 1.5;
+    while factor > 1.01 && evaluations < MAX_EVALUATIONS {
+        let mut improved = false;
+        for (index, parameter) in PARAMETERS.iter().enumerate() {
+            for scale in [factor, 1.0 / factor] {
+                let mut values = best.clone();
+                values[index] = (values[index] * scale).clamp(parameter.min, parameter.max);
+                if values[index] == best[index] {
+                    continue;
+                }
+                let value_cost = cost(&values);
+                evaluations += 1;
+                if value_cost < best_cost {
+                    best = values;
+                    best_cost = value_cost;
+                    improved = true;
+                    break;
+                }
+            }
+        }
+        if !improved {
+            factor = factor.sqrt();
+        }
+    }
+    log::debug!(
+        "calibration fit after {} evaluations: {:?}",
+        evaluations,
+        best
+    );
+
+    let mut fit = candidate(&best);
+    fit.error_before = miss_error(trials, base);
+    fit.error_after = miss_error(trials, &fit.apply(base));
+    Ok(fit)
+}
+
+// The flicks and corrections of a running calibration, fed by the controller on every release
+#[derive(Debug)]
+pub struct CalibrationSession {
+    bounds: Rect,
+    trials: Vec<Trial>,
+    // Release position and frames of a flick waiting for its correction
+    flick: Option<(Point, Vec<ContactFrame>)>,
+}
+
+impl CalibrationSession {
+    pub fn new(bounds: Rect) -> Self {
+        Self {
+            bounds,
+            trials: Vec::new(),
+            flick: None,
+        }
+    }
+
+    // Takes the frames captured up to a release and where the cursor was. Returns whether the
+    // release may glide: flicks do, corrections do not
+    pub fn release(&mut self, frames: Vec<ContactFrame>, position: Point) -> bool {
+        match self.flick.take() {
+            None => {
+                self.flick = Some((position, last_touch(frames)));
+                true
+            }
+            Some((release, frames)) => {
+                log::debug!("calibration trial {}", self.trials.len() + 1);
+                self.trials.push(Trial {
+                    bounds: self.bounds,
+                    release,
+                    target: position,
+                    frames,
+                });
+                false
+            }
+        }
+    }
+
+    pub fn trials(&self) -> &[Trial] {
+        &self.trials
+    }
+
+    // A flick still waiting for its correction is dropped
+    pub fn into_trials(self) -> Vec<Trial> {
+        self.trials
+    }
+}
+
+// The frames of the final touch, from the first contact to the lift-off
+fn last_touch(mut frames: Vec<ContactFrame>) -> Vec<ContactFrame> {
+    let end = frames
+        .iter()
+        .rposition(|frame| !frame.contacts.is_empty())
+        .map_or(0, |index| index + 1);
+    let start = frames[..end]
+        .iter()
+        .rposition(|frame| frame.contacts.is_empty())
+        .map_or(0, |index| index + 1);
+    frames.truncate((end + 1).min(frames.len()));
+    frames.drain(..start);
+    frames
+}
+
+// Text form: a `trial <x>,<y>,<width>,<height> <release x>,<y> <target x>,<y>` line for each trial
+// followed by its frames as in a trace
+pub fn format_trials(trials: &[Trial]) -> String {
+    let mut text = format!("{}\n", HEADER);
+    for trial in trials {
+        let _ = writeln!(
+            text,
+            "trial {},{},{},{} {:.2},{:.2} {:.2},{:.2}",
+            trial.bounds.origin.x,
+            trial.bounds.origin.y,
+            trial.bounds.size.width,
+            trial.bounds.size.height,
+            trial.release.x,
+            trial.release.y,
+            trial.target.x,
+            trial.target.y
+        );
+        for frame in &trial.frames {
+            let _ = writeln!(text, "{}", frame.to_line());
+        }
+    }
+    text
+}
+
+pub fn parse_trials<R: BufRead>(reader: R) -> io::Result<Vec<Trial>> {
+    let invalid = |index: usize, error: String| {
+        io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("line {}: {}", index + 1, error),
+        )
+    };
+    let mut trials: Vec<Trial> = Vec::new();
+    for (index, line) in reader.lines().enumerate() {
+        let line = line?;
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        if let Some(rest) = line.strip_prefix("trial") {
+            trials.push(parse_trial(rest).map_err(|error| invalid(index, error))?);
+            continue;
+        }
+        let frame = ContactFrame::parse_line(line).map_err(|error| invalid(index, error))?;
+        match trials.last_mut() {
+            Some(trial) => trial.frames.push(frame),
+            None => return Err(invalid(index, "frame before the first trial".to_string())),
+        }
+    }
+    Ok(trials)
+}
+
+fn parse_trial(fields: &str) -> Result<Trial, String> {
+    let numbers = |field: &str| {
+        field
+            .split(',')
+            .map(|value| value.parse::<Float>())
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|_| format!("invalid numbers `{}`", field))
+    };
+    let fields: Vec<&str> = fields.split_whitespace().collect();
+    let [bounds, release, target] = fields[..] else {
+        return Err("expected `trial <bounds> <release> <target>`".to_string());
+    };
+    let point = |field| match numbers(field)?[..] {
+        [x, y] => Ok(Point { x, y }),
+        _ => Err(format!("expected `<x>,<y>`, got `{}`", field)),
+    };
+    let bounds = match numbers(bounds)?[..] {
+        [x, y, width, height] => Rect {
+            origin: Point { x, y },
+            size: Size { width, height },
+        },
+        _ => {
+            return Err(format!(
+                "expected `<x>,<y>,<width>,<height>`, got `{}`",
+                bounds
+            ));
+        }
+    };
+    Ok(Trial {
+        bounds,
+        release: point(release)?,
+        target: point(target)?,
+        frames: Vec::new(),
+    })
+}
+
+pub fn read_trials(path: &Path) -> io::Result<Vec<Trial>> {
+    parse_trials(BufReader::new(File::open(path)?))
+}
+
+pub fn write_trials(path: &Path, trials: &[Trial]) -> io::Result<()> {
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    fs::write(path, format_trials(trials))
+}
@@ -0,0 +1,88 @@
+// One time base for touch and glide. Touch motion is integrated over the time between contact
+// frames, and glides run in fixed steps from the lift-off frame, catching up on whatever a late
+// tick missed but never more than `max_catch_up` at once
+
+// Wall clock in seconds since 2001, the CoreFoundation absolute time the app ticks on
+#[cfg(target_os = "macos")]
+pub fn wall_time() -> f64 {
+    objc2_core_foundation::CFAbsoluteTimeGetCurrent()
+}
+
+#[cfg(not(target_os = "macos"))]
+pub fn wall_time() -> f64 {
+    use std::time::{SystemTime, UNIX_EPOCH};
+    const CF_EPOCH: f64 = 978_307_200.0;
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0.0, |elapsed| elapsed.as_secs_f64() - CF_EPOCH)
+}
+
+#[derive(Debug, Default)]
+pub struct TimeBase {
+    // Frame clock minus wall clock, taken when a new frame is first seen
+    offset: f64,
+    latest_frame: Option<f64>,
+    // The last frame integrated while touching
+    consumed_frame: Option<f64>,
+    // How far the current glide has been integrated, in frame time
+    glide_time: Option<f64>,
+}
+
+impl TimeBase {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // Every tick, with the wall clock and the timestamp of the newest contact frame
+    pub fn observe(&mut self, frame: Option<f64>, wall: f64) {
+        if frame != self.latest_frame {
+            if let Some(frame) = frame {
+                self.offset = frame - wall;
+            }
+            self.latest_frame = frame;
+        }
+    }
+
+    pub fn now(&self, wall: f64) -> f64 {
+        wall + self.offset
+    }
+
+    // Frame time since the previous touch frame, None when no new frame arrived since then
+    pub fn touch_delta(&mut self, min_dt: f64, max_catch_up: f64) -> Option<f64> {
+        self.glide_time = None;
+        let frame = self.latest_frame?;
+        match self.consumed_frame.replace(frame) {
+            Some(previous) if frame <= previous => None,
+            Some(previous) => Some((frame - previous).clamp(min_dt, max_catch_up.max(min_dt))),
+            None => Some(min_dt),
+        }
+    }
+
+    // Number of `step` long glide steps due by `wall`. The first call of a glide starts counting
+    // at the lift-off frame
+    pub fn glide_steps(&mut self, wall: f64, step: f64, max_catch_up: f64) -> u32 {
+        self.consumed_frame = None;
+        let now = self.now(wall);
+        if step <= 0.0 {
+            return 0;
+        }
+        let time = self
+            .glide_time
+            .get_or_insert(self.latest_frame.map_or(now, |frame| frame.min(now)));
+        let mut behind = now - *time;
+        if behind > max_catch_up {
+            log::debug!("glide hitch: dropped {:.3}s", behind - max_catch_up);
+            *time = now - max_catch_up;
+            behind = max_catch_up;
+        }
+        let steps = (behind / step).floor().max(0.0) as u32;
+        *time += steps as f64 * step;
+        steps
+    }
+
+    // Between glides nothing is integrated
+    pub fn reset(&mut self) {
+        self.consumed_frame = None;
+        self.glide_time = None;
+    }
+}
@@ -0,0 +1,174 @@
+// Control socket: a Unix domain socket served from a background thread. Requests are handed to
+// the main thread through a channel and answered from the next tick, so commands never race the
+// engine. Slow work the main thread hands off is answered from a worker thread once it is done
+
+use crate::protocol::{Command, Response};
+use crate::scheduler::Waker;
+use std::fs::{self, Permissions};
+use std::io::{self, BufRead, BufReader, Write};
+use std::os::unix::fs::{FileTypeExt, MetadataExt, PermissionsExt};
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::thread;
+
+// How the main thread answers a command: right away, or with work for a worker thread
+pub enum Reply {
+    Now(Response),
+    Later(Box<dyn FnOnce() -> Response + Send>),
+}
+
+pub struct ControlRequest {
+    pub command: Command,
+    reply: Sender<Response>,
+}
+
+impl ControlRequest {
+    pub fn respond(self, response: Response) {
+        // The client may have hung up already, there is nobody left to tell
+        let _ = self.reply.send(response);
+    }
+
+    pub fn reply(self, reply: Reply) {
+        match reply {
+            Reply::Now(response) => self.respond(response),
+            Reply::Later(work) => {
+                let spawned = thread::Builder::new()
+                    .name("lapsus-control-work".to_string())
+                    .spawn(move || self.respond(work()));
+                if let Err(error) = spawned {
+                    log::warn!("control worker failed to start: {}", error);
+                }
+            }
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct ControlServer {
+    path: PathBuf,
+    requests: Receiver<ControlRequest>,
+}
+
+impl ControlServer {
+    pub fn bind(path: &Path) -> io::Result<Self> {
+        Self::bind_with_waker(path, None)
+    }
+
+    // The waker gets the main thread ticking again when a request arrives while it is idle
+    pub fn bind_with_waker(path: &Path, waker: Option<Arc<Waker>>) -> io::Result<Self> {
+        if let Ok(metadata) = fs::symlink_metadata(path) {
+            // Only ever replace a socket, never whatever else the path points at
+            if !metadata.file_type().is_socket() {
+                return Err(io::Error::new(
+                    io::ErrorKind::AlreadyExists,
+                    format!("{} exists and is not a socket", path.display()),
+                ));
+            }
+            // Nor one another user left in a shared directory, which could be a trap
+            if metadata.uid() != current_uid() {
+                return Err(io::Error::new(
+                    io::ErrorKind::PermissionDenied,
+                    format!("{} belongs to another user", path.display()),
+                ));
+            }
+            // A socket nobody answers on is left over from a crash
+            if UnixStream::connect(path).is_ok() {
+                return Err(io::Error::new(
+                    io::ErrorKind::AddrInUse,
+                    format!("{} is served by another instance", path.display()),
+                ));
+            }
+            fs::remove_file(path)?;
+        }
+        let listener = UnixListener::bind(path)?;
+        // Whoever can connect controls the cursor, so only this user may
+        if let Err(error) = fs::set_permissions(path, Permissions::from_mode(0o600)) {
+            let _ = fs::remove_file(path);
+            return Err(error);
+        }
+        let (sender, requests) = mpsc::channel();
+        thread::Builder::new()
+            .name("lapsus-control".to_string())
+            .spawn(move || accept_loop(listener, sender, waker))?;
+        log::info!("control socket listening on {}", path.display());
+        Ok(Self {
+            path: path.to_path_buf(),
+            requests,
+        })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn try_recv(&self) -> Option<ControlRequest> {
+        self.requests.try_recv().ok()
+    }
+}
+
+impl Drop for ControlServer {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.path);
+    }
+}
+
+fn current_uid() -> u32 {
+    unsafe { libc::getuid() }
+}
+
+fn accept_loop(listener: UnixListener, sender: Sender<ControlRequest>, waker: Option<Arc<Waker>>) {
+    for stream in listener.incoming() {
+        match stream {
+            Ok(stream) => {
+                let sender = sender.clone();
+                let waker = waker.clone();
+                let _ = thread::Builder::new()
+                    .name("lapsus-control-client".to_string())
+                    .spawn(move || {
+                        if let Err(error) = serve_client(stream, sender, waker) {
+                            log::debug!("control client error: {}", error);
+                        }
+                    });
+            }
+            Err(error) => log::warn!("control socket accept failed: {}", error),
+        }
+    }
+}
+
+fn serve_client(
+    stream: UnixStream,
+    sender: Sender<ControlRequest>,
+    waker: Option<Arc<Waker>>,
+) -> io::Result<()> {
+    let mut writer = stream.try_clone()?;
+    let reader = BufReader::new(stream);
+    for line in reader.lines() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let response = match Command::parse(&line) {
+            Ok(command) => dispatch(&sender, waker.as_deref(), command),
+            Err(error) => Response::error(error),
+        };
+        writeln!(writer, "{}", response.to_line())?;
+    }
+    Ok(())
+}
+
+fn dispatch(sender: &Sender<ControlRequest>, waker: Option<&Waker>, command: Command) -> Response {
+    log::debug!("control command: {}", command.to_line());
+    let (reply, response) = mpsc::channel();
+    let timeout = command.reply_timeout();
+    if sender.send(ControlRequest { command, reply }).is_err() {
+        return Response::error("lapsus is shutting down");
+    }
+    if let Some(waker) = waker {
+        waker.wake();
+    }
+    response
+        .recv_timeout(timeout)
+        .unwrap_or_else(|_| Response::error("timed out waiting for the main thread"))
+}
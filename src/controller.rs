@@ -1,15 +1,50 @@
-use crate::utils::{max, union_rect};
-use crate::{config, engine, trackpad};
-use cidre::cg::{Float, Point, Rect, Size, Vector};
+use crate::calibrate::{self, CalibrationSession, Trial};
+use crate::clock::TimeBase;
+use crate::control::Reply;
+use crate::cursor::MouseButton;
+use crate::device::{DeviceRules, DeviceSettings};
+use crate::focus::{AppAction, AppRules, FocusProvider, FocusTracker, WorkspaceFocusProvider};
+use crate::geometry::{Float, Point, Rect, Size, Vector};
+use crate::glide::GlideState;
+use crate::profile::{self, DEFAULT_PROFILE, Profile};
+use crate::protocol::{Command, Response};
+use crate::scheduler::Waker;
+use crate::suspend::{
+    ExternalMouseDetector, InputSnapshot, Modifiers, SuspendReason, SuspendRules,
+};
+use crate::trackpad::TouchSnapshot;
+use crate::utils::union_rect;
+use crate::{Config, config, engine, set_config, trace, trackpad};
 use std::fmt;
+use std::mem;
+use std::sync::Arc;
 
 pub struct Controller {
     pub engine: engine::Engine,
     monitor: trackpad::TrackpadMonitor,
     is_running: bool,
     last_update_timestamp: f64,
-    touch_ended_recently: bool,
+    time: TimeBase,
+    // Current state, read once per tick
+    touch: TouchSnapshot,
+    // Reused buffer for the frames drained each tick
+    frames: Vec<TouchSnapshot>,
     pub is_touching: bool,
+    paused: bool,
+    suspended: Option<SuspendReason>,
+    external_mouse: ExternalMouseDetector,
+    profile: String,
+    // Values changed with `set`, kept on top of whichever profile applies
+    overrides: Profile,
+    focus: Box<dyn FocusProvider>,
+    focus_tracker: FocusTracker,
+    device_rules: DeviceRules,
+    // The active trackpad and what the device rules say for it
+    device: Option<u64>,
+    device_settings: DeviceSettings,
+    // The trackpad the current touch came from
+    touch_device: Option<u64>,
+    calibration: Option<CalibrationSession>,
 }
 
 impl fmt::Debug for Controller {
@@ -17,21 +52,50 @@ impl fmt::Debug for Controller {
         f.debug_struct("Controller")
             .field("is_running", &self.is_running)
             .field("last_update_timestamp", &self.last_update_timestamp)
-            .field("touch_ended_recently", &self.touch_ended_recently)
+            .field("glide_state", &self.engine.glide_state())
             .field("is_touching", &self.is_touching)
+            .field("paused", &self.paused)
+            .field("suspended", &self.suspended)
+            .field("profile", &self.profile)
+            .field("app_action", &self.focus_tracker.action())
+            .field("device", &self.device)
             .finish()
     }
 }
 
 impl Controller {
     pub fn new() -> Self {
+        let app_rules = AppRules::load().unwrap_or_else(|error| {
+            log::warn!("app rules ignored: {}", error);
+            AppRules::default()
+        });
+        let device_rules = DeviceRules::load().unwrap_or_else(|error| {
+            log::warn!("device rules ignored: {}", error);
+            DeviceRules::default()
+        });
+        let monitor = trackpad::TrackpadMonitor::new();
+        monitor.set_device_rules(device_rules.clone());
         Self {
             engine: engine::Engine::new(),
-            monitor: trackpad::TrackpadMonitor::new(),
+            monitor,
             is_running: false,
             last_update_timestamp: 0.0,
-            touch_ended_recently: false,
+            time: TimeBase::new(),
+            touch: TouchSnapshot::EMPTY,
+            frames: Vec::new(),
             is_touching: false,
+            paused: false,
+            suspended: None,
+            external_mouse: ExternalMouseDetector::default(),
+            profile: DEFAULT_PROFILE.to_string(),
+            overrides: Profile::new("set"),
+            focus: Box::new(WorkspaceFocusProvider),
+            focus_tracker: FocusTracker::new(app_rules),
+            device_rules,
+            device: None,
+            device_settings: DeviceSettings::default(),
+            touch_device: None,
+            calibration: None,
         }
     }
 
@@ -42,13 +106,13 @@ impl Controller {
             self.monitor.start();
             self.update_desktop_bounds();
             self.update_engine_state();
-            self.is_touching = self.monitor.is_touching();
+            self.touch = self.monitor.snapshot();
+            self.is_touching = self.touch.is_touching;
         }
     }
 
     pub fn begin_touch(&mut self, position: Point) {
         self.is_touching = true;
-        self.touch_ended_recently = false;
         self.engine.begin_touch(position);
     }
 
@@ -64,17 +128,29 @@ impl Controller {
 
     pub fn end_touch(&mut self) {
         self.is_touching = false;
-        self.touch_ended_recently = true;
     }
 
     pub fn handle_no_touch(&mut self, position: Point, delta_time: Float, suppress_glide: bool) {
-        self.engine.handle_no_touch(
-            position,
-            delta_time,
-            suppress_glide,
-            self.touch_ended_recently,
-        );
-        self.touch_ended_recently = false;
+        self.engine
+            .handle_no_touch(position, delta_time, suppress_glide);
+    }
+
+    pub fn set_waker(&self, waker: Arc<Waker>) {
+        self.monitor.set_waker(waker);
+    }
+
+    // Whether the next tick has work to do: a finger is down or a glide is still running
+    pub fn is_busy(&self) -> bool {
+        self.touch.is_touching
+            || !matches!(
+                self.engine.glide_state(),
+                GlideState::Idle | GlideState::Suppressed
+            )
+    }
+
+    // Nothing was tracked while the ticks were stopped, so pick up the cursor where it is now
+    pub fn resume_from_idle(&mut self) {
+        self.update_engine_state();
     }
 
     pub fn stop(&mut self) {
@@ -84,40 +160,389 @@ impl Controller {
         }
     }
 
+    // While paused the monitor keeps running but the cursor is left entirely to the OS
+    pub fn set_paused(&mut self, paused: bool) {
+        if self.paused == paused {
+            return;
+        }
+        self.paused = paused;
+        log::info!("{}", if paused { "paused" } else { "resumed" });
+        if paused {
+            self.engine.suspend();
+        } else {
+            self.engine.resume();
+        }
+        self.update_engine_state();
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.paused
+    }
+
+    pub fn toggle_paused(&mut self) {
+        self.set_paused(!self.paused);
+    }
+
+    pub fn suspend_reason(&self) -> Option<SuspendReason> {
+        self.suspended
+    }
+
+    pub fn apply_profile(&mut self, name: &str) -> Result<(), String> {
+        let profile = profile::load(name)?;
+        let config = self.layered(&profile)?;
+        log::info!("profile {}", profile.name);
+        self.profile = profile.name;
+        // An app or device rule profile keeps precedence while it applies
+        if self.rule_profile().is_none() {
+            set_config(config);
+        }
+        Ok(())
+    }
+
+    // The config `profile` selects with the runtime overrides, and while calibrating the session
+    // settings on top of both
+    fn layered(&self, profile: &Profile) -> Result<Config, String> {
+        let config = profile.layered(&self.overrides)?;
+        Ok(self.with_session_settings(config))
+    }
+
+    fn with_session_settings(&self, config: Config) -> Config {
+        match self.calibration {
+            Some(_) => calibrate::session_settings()
+                .apply(&config)
+                .expect("session settings are valid config values"),
+            None => config,
+        }
+    }
+
+    fn app_profile(&self) -> Option<&str> {
+        match self.focus_tracker.action() {
+            Some(AppAction::Profile(name)) => Some(name),
+            _ => None,
+        }
+    }
+
+    // App rules win over device rules, which win over the selected profile
+    fn rule_profile(&self) -> Option<&str> {
+        self.app_profile()
+            .or(self.device_settings.profile.as_deref())
+    }
+
+    fn apply_rule_profile(&mut self) {
+        let name = self.rule_profile().unwrap_or(&self.profile).to_string();
+        match profile::load(&name).and_then(|profile| self.layered(&profile)) {
+            Ok(config) => {
+                log::debug!(
+                    "app rule action {:?}, device {:?}, profile {}",
+                    self.focus_tracker.action(),
+                    self.device,
+                    name
+                );
+                set_config(config);
+            }
+            Err(error) => log::warn!("rule profile: {}", error),
+        }
+    }
+
+    fn set_active_device(&mut self, device: Option<u64>) {
+        if device == self.device {
+            return;
+        }
+        self.device = device;
+        let info = device.and_then(|id| self.monitor.device(id));
+        let settings = info
+            .as_ref()
+            .map(|info| self.device_rules.settings_for(info))
+            .unwrap_or_default();
+        if let Some(info) = &info {
+            log::debug!("active device {}: {:?}", info, settings);
+        }
+        self.engine.set_device_gain(settings.gain);
+        let profile_changed = settings.profile != self.device_settings.profile;
+        self.device_settings = settings;
+        if profile_changed {
+            self.apply_rule_profile();
+        }
+    }
+
+    // Anything slow is handed back as work for a worker thread, so the tick never stalls on it
+    pub fn execute(&mut self, command: &Command) -> Reply {
+        let response = match command {
+            Command::Status => self.status(),
+            Command::Pause => {
+                self.set_paused(true);
+                Response::ok().with("paused", self.paused)
+            }
+            Command::Resume => {
+                self.set_paused(false);
+                Response::ok().with("paused", self.paused)
+            }
+            Command::Profile(name) => match self.apply_profile(name) {
+                Ok(()) => Response::ok().with("profile", &self.profile),
+                Err(error) => Response::error(error),
+            },
+            Command::Get(key) => match config().get(key) {
+                Some(value) => Response::ok().with(key, value),
+                None => Response::error(format!("unknown config key `{}`", key)),
+            },
+            Command::Set(key, value) => {
+                let mut updated = Config::clone(&config());
+                match updated.set(key, value) {
+                    Ok(()) => {
+                        self.overrides.set(key, value);
+                        set_config(self.with_session_settings(updated));
+                        Response::ok().with(key, value)
+                    }
+                    Err(error) => Response::error(error),
+                }
+            }
+            Command::RecordStart(path) => match self.monitor.start_recording(path) {
+                Ok(()) => Response::ok().with("recording", path.display()),
+                Err(error) => Response::error(format!("{}: {}", path.display(), error)),
+            },
+            Command::RecordStop => match self.monitor.stop_recording() {
+                Ok(Some((path, frames))) => Response::ok()
+                    .with("path", path.display())
+                    .with("frames", frames),
+                Ok(None) => Response::error("not recording"),
+                Err(error) => Response::error(error),
+            },
+            Command::CalibrateStart => {
+                if self.calibration.is_some() {
+                    return Reply::Now(Response::error("already calibrating"));
+                }
+                self.monitor.start_capture();
+                self.calibration = Some(CalibrationSession::new(self.engine.desktop_bounds()));
+                set_config(self.with_session_settings(Config::clone(&config())));
+                Response::ok().with("calibrating", true)
+            }
+            Command::CalibrateSave(name) => {
+                // Fitted from the session settings, before the profile gets its own back
+                let config = config();
+                match self.calibration.take() {
+                    Some(session) => {
+                        self.monitor.stop_capture();
+                        self.apply_rule_profile();
+                        let (name, trials) = (name.clone(), session.into_trials());
+                        return Reply::Later(Box::new(move || {
+                            save_calibration(&name, trials, &config)
+                        }));
+                    }
+                    None => Response::error("not calibrating"),
+                }
+            }
+            Command::CalibrateCancel => match self.calibration.take() {
+                Some(session) => {
+                    self.monitor.stop_capture();
+                    self.apply_rule_profile();
+                    Response::ok().with("discarded", session.trials().len())
+                }
+                None => Response::error("not calibrating"),
+            },
+            Command::Replay(path) => {
+                let frames = match trace::read_trace(path) {
+                    Ok(frames) => frames,
+                    Err(error) => {
+                        return Reply::Now(Response::error(format!(
+                            "{}: {}",
+                            path.display(),
+                            error
+                        )));
+                    }
+                };
+                let count = frames.len();
+                match self.monitor.replay(frames) {
+                    Ok(()) => Response::ok()
+                        .with("replaying", path.display())
+                        .with("frames", count),
+                    Err(error) => Response::error(error),
+                }
+            }
+        };
+        Reply::Now(response)
+    }
+
+    fn status(&self) -> Response {
+        let position = self.engine.position();
+        let velocity = self.engine.velocity();
+        Response::ok()
+            .with("paused", self.paused)
+            .with(
+                "suspended",
+                self.suspended
+                    .map_or("none".to_string(), |reason| reason.to_string()),
+            )
+            .with("profile", &self.profile)
+            .with("app_profile", self.app_profile().unwrap_or("none"))
+            .with(
+                "device",
+                self.device
+                    .and_then(|id| self.monitor.device(id))
+                    .map_or("none".to_string(), |info| info.to_string()),
+            )
+            .with(
+                "device_profile",
+                self.device_settings.profile.as_deref().unwrap_or("none"),
+            )
+            .with("is_touching", self.touch.is_touching)
+            .with("is_gliding", self.engine.is_gliding())
+            .with("glide_state", self.engine.glide_state())
+            .with("velocity", format!("{:.1},{:.1}", velocity.dx, velocity.dy))
+            .with("position", format!("{:.1},{:.1}", position.x, position.y))
+            .with("recording", self.monitor.is_recording())
+            .with("dropped_frames", self.monitor.dropped_frames())
+            .with(
+                "calibration_trials",
+                self.calibration
+                    .as_ref()
+                    .map_or("off".to_string(), |session| {
+                        session.trials().len().to_string()
+                    }),
+            )
+    }
+
     pub fn update_state(&mut self) {
         let now = objc2_core_foundation::CFAbsoluteTimeGetCurrent();
-        let delta_seconds = max(now - self.last_update_timestamp, config().min_dt);
         self.last_update_timestamp = now;
-        let delta_time = delta_seconds;
+        let mut frames = mem::take(&mut self.frames);
+        self.monitor.drain_frames(&mut frames);
+        let touch = *frames
+            .last()
+            .expect("drained frames end with the current state");
+        self.touch = touch;
+        self.is_touching = touch.is_touching;
+        self.set_active_device(touch.device);
+        self.process_frames(&frames, now);
+        self.frames = frames;
+    }
+
+    fn process_frames(&mut self, frames: &[TouchSnapshot], now: f64) {
+        let touch = self.touch;
+        if self.paused {
+            self.time.reset();
+            return;
+        }
         let ns_mouse_location = objc2_app_kit::NSEvent::mouseLocation();
         let physical_position = Point {
             x: ns_mouse_location.x,
             y: ns_mouse_location.y,
         };
-        let is_touching = self.monitor.is_touching();
+        let is_touching = touch.is_touching;
 
-        if is_touching {
-            if !self.touch_ended_recently {
-                log::debug!("touch begin detected");
-                self.engine.begin_touch(physical_position);
+        let input = self.read_input(physical_position, is_touching, now);
+        self.engine
+            .set_pressed_button(MouseButton::from_mask(input.mouse_buttons));
+        if self
+            .focus_tracker
+            .poll(self.focus.as_ref(), now, config().app_rule_interval)
+        {
+            self.apply_rule_profile();
+        }
+        let app_disabled = self.focus_tracker.action() == Some(&AppAction::Disable);
+        let suspended = SuspendRules::from_config(&config())
+            .evaluate(&input)
+            .or(app_disabled.then_some(SuspendReason::Application));
+        if suspended != self.suspended {
+            match suspended {
+                Some(reason) => log::debug!("suspended: {}", reason),
+                None => log::debug!("suspend cleared"),
             }
-            self.engine.handle_touch(
-                physical_position,
-                delta_time,
-                self.monitor.current_normalized_velocity(),
-            );
-        } else {
-            if self.touch_ended_recently {
-                log::debug!("touch end detected");
+            self.suspended = suspended;
+        }
+        if self.suspended.is_some() {
+            // Pass through, but keep following the cursor so nothing jumps when the rule clears
+            self.engine.suspend();
+            self.engine.update_engine_state(physical_position);
+            self.time.reset();
+            return;
+        }
+        self.engine.resume();
+
+        // Every touch begin and end since the last tick goes through the engine in order, while a
+        // run of touching frames only moves it once, with the newest frame
+        let config = config();
+        for (index, frame) in frames.iter().enumerate() {
+            self.time.observe(frame.frame_timestamp(), now);
+            if frame.is_touching {
+                if frames.get(index + 1).is_some_and(|next| next.is_touching) {
+                    continue;
+                }
+                // Another trackpad taking over mid-touch starts the touch afresh, its centroid
+                // and frame clock have nothing to do with the previous one's
+                if frame.device != self.touch_device
+                    && self.engine.glide_state() == GlideState::Touching
+                {
+                    log::debug!("touch moved to device {:?}", frame.device);
+                    self.time.reset();
+                    self.engine.begin_touch(physical_position);
+                }
+                self.touch_device = frame.device;
+                // Touch motion only advances when a new frame came in
+                let Some(delta_time) = self.time.touch_delta(config.min_dt, config.max_catch_up)
+                else {
+                    continue;
+                };
+                self.engine.set_angular_velocity(frame.angular_velocity);
+                self.engine.set_physical_velocity(frame.physical_velocity);
+                self.engine
+                    .set_touch_centroid(frame.centroid, frame.contacts);
+                self.engine.handle_touch(
+                    physical_position,
+                    delta_time,
+                    Some(frame.normalized_velocity),
+                );
+            } else {
+                let mut suppress_glide = frame.suppresses_glide(now);
+                if self.engine.glide_state() == GlideState::Touching
+                    && let Some(session) = self.calibration.as_mut()
+                {
+                    let captured = self.monitor.take_captured(frame.timestamp);
+                    // Corrections land exactly where the user put the cursor
+                    suppress_glide |= !session.release(captured, physical_position);
+                }
+                // Decides on the release, the glide itself is stepped below
+                self.engine.set_contact_weight(frame.contact_weight);
+                self.engine
+                    .handle_no_touch(physical_position, 0.0, suppress_glide);
+            }
+        }
+        if !touch.is_touching {
+            // Catch the glide up from the lift-off frame
+            if self.engine.is_gliding() {
+                let steps = self
+                    .time
+                    .glide_steps(now, config.glide_step, config.max_catch_up);
+                self.engine.step_glide(steps, config.glide_step);
+            } else {
+                self.time.reset();
             }
-            self.engine.handle_no_touch(
-                physical_position,
-                delta_time,
-                self.monitor.should_suppress_glide(),
-                self.touch_ended_recently,
-            );
         }
-        self.touch_ended_recently = is_touching;
+    }
+
+    fn read_input(
+        &mut self,
+        physical_position: Point,
+        is_touching: bool,
+        now: f64,
+    ) -> InputSnapshot {
+        let flags = objc2_app_kit::NSEvent::modifierFlags_class();
+        let external_mouse_active = self.external_mouse.update(
+            physical_position,
+            self.engine.position(),
+            is_touching,
+            now,
+            config().external_mouse_hold,
+        );
+        InputSnapshot {
+            mouse_buttons: objc2_app_kit::NSEvent::pressedMouseButtons(),
+            modifiers: Modifiers {
+                shift: flags.contains(objc2_app_kit::NSEventModifierFlags::Shift),
+                control: flags.contains(objc2_app_kit::NSEventModifierFlags::Control),
+                option: flags.contains(objc2_app_kit::NSEventModifierFlags::Option),
+                command: flags.contains(objc2_app_kit::NSEventModifierFlags::Command),
+            },
+            external_mouse_active,
+        }
     }
 
     fn update_engine_state(&mut self) {
@@ -167,3 +592,35 @@ impl Controller {
         self.engine.update_desktop_bounds(bounds);
     }
 }
+
+// Fits the trials, saves the result as profile `name` and keeps the trials next to the
+// profiles so they can be fitted again offline
+fn save_calibration(name: &str, trials: Vec<Trial>, config: &Config) -> Response {
+    let fit = match calibrate::fit(&trials, config) {
+        Ok(fit) => fit,
+        Err(error) => return Response::error(error),
+    };
+    let trials_path = profile::profile_dir().join(format!("{}.trials", name));
+    if let Err(error) = calibrate::write_trials(&trials_path, &trials) {
+        log::warn!("{}: {}", trials_path.display(), error);
+    }
+    let path = match profile::save(&fit.profile(name)) {
+        Ok(path) => path,
+        Err(error) => return Response::error(format!("saving profile {}: {}", name, error)),
+    };
+    log::info!(
+        "calibrated profile {} from {} trials, error {:.1} -> {:.1} px",
+        name,
+        trials.len(),
+        fit.error_before,
+        fit.error_after
+    );
+    fit.values.iter().fold(
+        Response::ok()
+            .with("profile", path.display())
+            .with("trials", trials.len())
+            .with("error_before", format!("{:.1}", fit.error_before))
+            .with("error_after", format!("{:.1}", fit.error_after)),
+        |response, (key, value)| response.with(key, format!("{:.3}", value)),
+    )
+}
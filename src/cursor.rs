@@ -0,0 +1,177 @@
+use crate::geometry::{Point, Vector};
+
+#[cfg(target_os = "macos")]
+pub use display::DisplayCursorSink;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MouseButton {
+    Left,
+    Right,
+    Other,
+}
+
+impl MouseButton {
+    // From a pressed buttons mask where bit 0 is the primary button
+    pub fn from_mask(mask: usize) -> Option<Self> {
+        if mask & 1 != 0 {
+            Some(MouseButton::Left)
+        } else if mask & 2 != 0 {
+            Some(MouseButton::Right)
+        } else if mask != 0 {
+            Some(MouseButton::Other)
+        } else {
+            None
+        }
+    }
+}
+
+// Anything that can put the cursor where the engine wants it
+pub trait CursorSink {
+    // `position` is in desktop coordinates (origin bottom-left, y up) and `delta` is the whole pixel
+    // motion the engine added since the last call, which is zero when it only re-asserts the position
+    fn move_cursor(&mut self, position: Point, delta: Vector);
+
+    // Same motion while `button` is held. Sinks that cannot tell the difference just move
+    fn drag_cursor(&mut self, position: Point, delta: Vector, _button: MouseButton) {
+        self.move_cursor(position, delta);
+    }
+
+    // While owned, the engine alone moves the cursor and the OS stops applying device motion to it
+    fn set_pointer_owned(&mut self, _owned: bool) {}
+}
+
+// Goes nowhere, for headless simulations and platforms without an output
+pub struct NullCursorSink;
+
+impl CursorSink for NullCursorSink {
+    fn move_cursor(&mut self, _position: Point, _delta: Vector) {}
+}
+
+// The output of the platform Lapsus runs on
+#[cfg(target_os = "macos")]
+pub fn default_sink() -> Box<dyn CursorSink> {
+    Box::new(DisplayCursorSink)
+}
+
+#[cfg(target_os = "linux")]
+pub fn default_sink() -> Box<dyn CursorSink> {
+    match crate::uinput::UinputCursorSink::open() {
+        Ok(sink) => Box::new(sink),
+        Err(error) => {
+            log::warn!("uinput unavailable, the cursor will not move: {}", error);
+            Box::new(NullCursorSink)
+        }
+    }
+}
+
+#[cfg(not(any(target_os = "macos", target_os = "linux")))]
+pub fn default_sink() -> Box<dyn CursorSink> {
+    Box::new(NullCursorSink)
+}
+
+// Keeps the fractional part of relative motion so that slow deltas still add up to whole pixels
+#[derive(Debug, Default, Clone, Copy)]
+pub struct SubpixelAccumulator {
+    remainder: Vector,
+}
+
+impl SubpixelAccumulator {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // Returns a whole pixel step, the running sum of steps stays within half a pixel of the running sum of deltas
+    pub fn step(&mut self, delta: Vector) -> Vector {
+        let x = self.remainder.dx + delta.dx;
+        let y = self.remainder.dy + delta.dy;
+        let step = Vector {
+            dx: x.round(),
+            dy: y.round(),
+        };
+        self.remainder = Vector {
+            dx: x - step.dx,
+            dy: y - step.dy,
+        };
+        step
+    }
+
+    pub fn remainder(&self) -> Vector {
+        self.remainder
+    }
+
+    pub fn reset(&mut self) {
+        self.remainder = Vector { dx: 0.0, dy: 0.0 };
+    }
+}
+
+#[cfg(target_os = "macos")]
+mod display {
+    use super::{CursorSink, MouseButton};
+    use crate::geometry::{Point, Vector};
+    use core_graphics::display;
+    use core_graphics::event::{
+        CGEvent, CGEventTapLocation, CGEventType, CGMouseButton, EventField,
+    };
+    use core_graphics::event_source::{CGEventSource, CGEventSourceStateID};
+    use objc2_app_kit::NSScreen;
+
+    // Warps the cursor with CoreGraphics, or posts drag events so whatever is dragged follows
+    pub struct DisplayCursorSink;
+
+    impl DisplayCursorSink {
+        // Desktop coordinates to the main display's top-left based coordinates
+        fn display_point(position: Point) -> Option<display::CGPoint> {
+            let mtm =
+                objc2_foundation::MainThreadMarker::new().expect("must be on the main thread");
+            let screen = NSScreen::mainScreen(mtm)?;
+            let local_x = position.x - screen.frame().min().x;
+            let local_y_from_bottom = position.y - screen.frame().min().y;
+            let local_y = screen.frame().size.height - local_y_from_bottom;
+            Some(display::CGPoint {
+                x: local_x,
+                y: local_y,
+            })
+        }
+    }
+
+    impl CursorSink for DisplayCursorSink {
+        fn move_cursor(&mut self, position: Point, _delta: Vector) {
+            if let Some(point) = Self::display_point(position) {
+                let _error =
+                    display::CGDisplay::move_cursor_to_point(&display::CGDisplay::main(), point);
+            }
+        }
+
+        fn drag_cursor(&mut self, position: Point, delta: Vector, button: MouseButton) {
+            let Some(point) = Self::display_point(position) else {
+                return;
+            };
+            let (event_type, mouse_button) = match button {
+                MouseButton::Left => (CGEventType::LeftMouseDragged, CGMouseButton::Left),
+                MouseButton::Right => (CGEventType::RightMouseDragged, CGMouseButton::Right),
+                MouseButton::Other => (CGEventType::OtherMouseDragged, CGMouseButton::Center),
+            };
+            let Ok(source) = CGEventSource::new(CGEventSourceStateID::HIDSystemState) else {
+                log::warn!("cannot create event source for drag");
+                return;
+            };
+            match CGEvent::new_mouse_event(source, event_type, point, mouse_button) {
+                Ok(event) => {
+                    // Display space is y down
+                    event.set_integer_value_field(EventField::MOUSE_EVENT_DELTA_X, delta.dx as i64);
+                    event
+                        .set_integer_value_field(EventField::MOUSE_EVENT_DELTA_Y, -delta.dy as i64);
+                    event.post(CGEventTapLocation::HID);
+                }
+                Err(_) => log::warn!("cannot create drag event"),
+            }
+        }
+
+        fn set_pointer_owned(&mut self, owned: bool) {
+            // Detaches the hardware from the cursor, so OS acceleration no longer applies to it
+            if display::CGDisplay::associate_mouse_and_mouse_cursor_position(!owned).is_err() {
+                log::warn!("cannot change cursor association");
+            }
+        }
+    }
+}
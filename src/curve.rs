@@ -0,0 +1,68 @@
+// Pointer acceleration while touching: a piecewise-linear gain over input speed. Written in config
+// as `speed:gain` control points, e.g. `0:0.6,300:1,1500:2`, or as the name of a preset
+
+use crate::geometry::Float;
+use crate::piecewise::{CurveShape, Monotonicity, PiecewiseLinear};
+use std::fmt;
+use std::str::FromStr;
+
+// Gains stay positive and the output speed never drops as the input speeds up
+const SHAPE: CurveShape = CurveShape {
+    input: "speed",
+    output: "gain",
+    inputs: 0.0..=Float::MAX,
+    outputs: Float::MIN_POSITIVE..=Float::MAX,
+    monotonicity: Monotonicity::NondecreasingProduct,
+    presets: PRESETS,
+};
+
+const PRESETS: &[(&str, &[(Float, Float)])] = &[
+    ("linear", &[(0.0, 1.0)]),
+    ("precise", &[(0.0, 0.4), (200.0, 0.8), (1000.0, 1.2)]),
+    (
+        "trackball",
+        &[(0.0, 0.6), (300.0, 1.0), (1500.0, 2.0), (4000.0, 3.0)],
+    ),
+    ("fast", &[(0.0, 1.0), (500.0, 2.0), (2000.0, 4.0)]),
+];
+
+// Input speed in px/s to gain
+#[derive(Debug, Clone, PartialEq)]
+pub struct AccelerationCurve(PiecewiseLinear);
+
+impl AccelerationCurve {
+    pub fn new(points: Vec<(Float, Float)>) -> Result<Self, String> {
+        PiecewiseLinear::new(&SHAPE, points).map(Self)
+    }
+
+    pub fn off() -> Self {
+        Self(PiecewiseLinear::off())
+    }
+
+    pub fn preset(name: &str) -> Option<Self> {
+        PiecewiseLinear::preset(&SHAPE, name).map(Self)
+    }
+
+    pub fn is_off(&self) -> bool {
+        self.0.is_off()
+    }
+
+    // Gain at `speed`, flat beyond the first and last control points
+    pub fn gain(&self, speed: Float) -> Float {
+        self.0.value(speed)
+    }
+}
+
+impl FromStr for AccelerationCurve {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        PiecewiseLinear::parse(&SHAPE, value).map(Self)
+    }
+}
+
+impl fmt::Display for AccelerationCurve {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.0.fmt(f)
+    }
+}
@@ -0,0 +1,324 @@
+// Multitouch devices and per-device settings. Settings live in the `device_rules` file of the
+// support directory, one device per line followed by the settings to override:
+//
+//     builtin gain 0.8
+//     external profile precise
+//     id:0x200000001000000 gain 1.2 profile fast
+//     family:128 gain 1.1 size 160x115
+//
+// `size` is the touch surface in millimetres, for devices that report it wrong or not at all
+//
+// Every matching line applies in order, so later lines win. Devices are enumerated again when the
+// system reports a trackpad coming or going, or every `device_poll_interval` seconds where it
+// cannot, so trackpads can come and go while Lapsus runs
+
+use crate::frame::Contact;
+use crate::geometry::Float;
+use crate::profile;
+use std::fmt;
+use std::fs;
+use std::io;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct DeviceInfo {
+    pub id: u64,
+    pub family: i32,
+    pub builtin: bool,
+    // Sensor surface in millimetres, zero when the device does not report it
+    pub width_mm: Float,
+    pub height_mm: Float,
+}
+
+impl DeviceInfo {
+    // Width and height of the surface in millimetres, if known
+    pub fn size_mm(&self) -> Option<(Float, Float)> {
+        (self.width_mm > 0.0 && self.height_mm > 0.0).then_some((self.width_mm, self.height_mm))
+    }
+}
+
+impl fmt::Display for DeviceInfo {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} {:#x} (family {}, {:.0}x{:.0} mm)",
+            if self.builtin { "built-in" } else { "external" },
+            self.id,
+            self.family,
+            self.width_mm,
+            self.height_mm
+        )
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum DeviceChange {
+    Added(DeviceInfo),
+    Removed(DeviceInfo),
+}
+
+impl fmt::Display for DeviceChange {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DeviceChange::Added(info) => write!(f, "trackpad added: {}", info),
+            DeviceChange::Removed(info) => write!(f, "trackpad removed: {}", info),
+        }
+    }
+}
+
+// Receives the contacts and timestamp of every frame
+pub type FrameCallback = Box<dyn Fn(&mut dyn Iterator<Item = Contact>, f64) + Send + Sync>;
+
+pub trait TouchDevice {
+    fn info(&self) -> DeviceInfo;
+    fn start(&mut self, on_frame: FrameCallback);
+    fn stop(&mut self);
+}
+
+// Keeps the change notifications of `DeviceEnumerator::watch` coming until dropped
+pub type DeviceWatch = Box<dyn Send>;
+
+pub trait DeviceEnumerator: Send {
+    // Every device connected right now
+    fn enumerate(&mut self) -> Vec<Box<dyn TouchDevice>>;
+
+    // Calls `on_change`, from any thread, whenever a device may have come or gone. None when
+    // the platform does not tell, then the devices are polled
+    fn watch(&mut self, _on_change: Box<dyn Fn() + Send + Sync>) -> Option<DeviceWatch> {
+        None
+    }
+}
+
+// For headless runs, nothing is ever connected
+pub struct NoDevices;
+
+impl DeviceEnumerator for NoDevices {
+    fn enumerate(&mut self) -> Vec<Box<dyn TouchDevice>> {
+        Vec::new()
+    }
+}
+
+// The devices currently delivering frames, kept in sync with an enumerator
+#[derive(Default)]
+pub struct DeviceRegistry {
+    devices: Vec<(DeviceInfo, Box<dyn TouchDevice>)>,
+}
+
+impl DeviceRegistry {
+    pub fn devices(&self) -> Vec<DeviceInfo> {
+        self.devices.iter().map(|(info, _)| info.clone()).collect()
+    }
+
+    // Starts devices that appeared, with a callback from `connect`, and stops the ones that are
+    // gone. Devices seen before keep their registration
+    pub fn refresh(
+        &mut self,
+        enumerator: &mut dyn DeviceEnumerator,
+        mut connect: impl FnMut(&DeviceInfo) -> FrameCallback,
+    ) -> Vec<DeviceChange> {
+        let found: Vec<(DeviceInfo, Box<dyn TouchDevice>)> = enumerator
+            .enumerate()
+            .into_iter()
+            .map(|device| (device.info(), device))
+            .collect();
+        let mut changes = Vec::new();
+        self.devices.retain_mut(|(info, device)| {
+            let present = found.iter().any(|(found, _)| found.id == info.id);
+            if !present {
+                device.stop();
+                changes.push(DeviceChange::Removed(info.clone()));
+            }
+            present
+        });
+        for (info, mut device) in found {
+            if self.devices.iter().any(|(known, _)| known.id == info.id) {
+                continue;
+            }
+            device.start(connect(&info));
+            changes.push(DeviceChange::Added(info.clone()));
+            self.devices.push((info, device));
+        }
+        changes
+    }
+
+    pub fn stop_all(&mut self) -> Vec<DeviceChange> {
+        self.devices
+            .drain(..)
+            .map(|(info, mut device)| {
+                device.stop();
+                DeviceChange::Removed(info)
+            })
+            .collect()
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DeviceMatcher {
+    Builtin,
+    External,
+    Id(u64),
+    Family(i32),
+}
+
+impl DeviceMatcher {
+    pub fn parse(value: &str) -> Result<Self, String> {
+        let parse_id = |id: &str| match id.strip_prefix("0x") {
+            Some(hex) => u64::from_str_radix(hex, 16).ok(),
+            None => id.parse().ok(),
+        };
+        let matcher = match value {
+            "builtin" => Some(DeviceMatcher::Builtin),
+            "external" => Some(DeviceMatcher::External),
+            _ => match value.split_once(':') {
+                Some(("id", id)) => parse_id(id).map(DeviceMatcher::Id),
+                Some(("family", family)) => family.parse().ok().map(DeviceMatcher::Family),
+                _ => None,
+            },
+        };
+        matcher.ok_or_else(|| {
+            format!(
+                "expected builtin, external, id:<id> or family:<id>, got `{}`",
+                value
+            )
+        })
+    }
+
+    pub fn matches(&self, device: &DeviceInfo) -> bool {
+        match *self {
+            DeviceMatcher::Builtin => device.builtin,
+            DeviceMatcher::External => !device.builtin,
+            DeviceMatcher::Id(id) => device.id == id,
+            DeviceMatcher::Family(family) => device.family == family,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct DeviceRule {
+    pub matcher: DeviceMatcher,
+    pub gain: Option<Float>,
+    pub profile: Option<String>,
+    pub size_mm: Option<(Float, Float)>,
+}
+
+impl DeviceRule {
+    pub fn parse(line: &str) -> Result<Self, String> {
+        let mut fields = line.split_whitespace();
+        let matcher = DeviceMatcher::parse(fields.next().unwrap_or_default())?;
+        let mut rule = Self {
+            matcher,
+            gain: None,
+            profile: None,
+            size_mm: None,
+        };
+        while let Some(key) = fields.next() {
+            let value = fields
+                .next()
+                .ok_or_else(|| format!("missing value for `{}`", key))?;
+            match key {
+                "gain" => match value.parse::<Float>() {
+                    Ok(gain) if gain.is_finite() && gain > 0.0 => rule.gain = Some(gain),
+                    _ => return Err(format!("invalid gain `{}`", value)),
+                },
+                "profile" => rule.profile = Some(value.to_string()),
+                "size" => {
+                    let size = value.split_once('x').and_then(|(width, height)| {
+                        Some((width.parse::<Float>().ok()?, height.parse::<Float>().ok()?))
+                    });
+                    match size {
+                        Some((width, height)) if width > 0.0 && height > 0.0 => {
+                            rule.size_mm = Some((width, height))
+                        }
+                        _ => return Err(format!("expected `<width>x<height>`, got `{}`", value)),
+                    }
+                }
+                _ => {
+                    return Err(format!(
+                        "expected `gain`, `profile` or `size`, got `{}`",
+                        key
+                    ));
+                }
+            }
+        }
+        if rule.gain.is_none() && rule.profile.is_none() && rule.size_mm.is_none() {
+            return Err(format!("no settings in `{}`", line));
+        }
+        Ok(rule)
+    }
+}
+
+// What the rules say for one device
+#[derive(Debug, Clone, PartialEq)]
+pub struct DeviceSettings {
+    // Multiplies `trackpad_velocity_gain`
+    pub gain: Float,
+    pub profile: Option<String>,
+    // Overrides the size the device reports
+    pub size_mm: Option<(Float, Float)>,
+}
+
+impl Default for DeviceSettings {
+    fn default() -> Self {
+        Self {
+            gain: 1.0,
+            profile: None,
+            size_mm: None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct DeviceRules {
+    rules: Vec<DeviceRule>,
+}
+
+impl DeviceRules {
+    pub fn new(rules: Vec<DeviceRule>) -> Self {
+        Self { rules }
+    }
+
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let mut rules = Vec::new();
+        for (index, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            rules.push(
+                DeviceRule::parse(line)
+                    .map_err(|error| format!("line {}: {}", index + 1, error))?,
+            );
+        }
+        Ok(Self::new(rules))
+    }
+
+    pub fn load() -> Result<Self, String> {
+        let path = profile::support_dir().join("device_rules");
+        match fs::read_to_string(&path) {
+            Ok(text) => {
+                Self::parse(&text).map_err(|error| format!("{}: {}", path.display(), error))
+            }
+            Err(error) if error.kind() == io::ErrorKind::NotFound => Ok(Self::default()),
+            Err(error) => Err(format!("{}: {}", path.display(), error)),
+        }
+    }
+
+    pub fn settings_for(&self, device: &DeviceInfo) -> DeviceSettings {
+        let mut settings = DeviceSettings::default();
+        for rule in self
+            .rules
+            .iter()
+            .filter(|rule| rule.matcher.matches(device))
+        {
+            if let Some(gain) = rule.gain {
+                settings.gain = gain;
+            }
+            if let Some(profile) = &rule.profile {
+                settings.profile = Some(profile.clone());
+            }
+            if rule.size_mm.is_some() {
+                settings.size_mm = rule.size_mm;
+            }
+        }
+        settings
+    }
+}
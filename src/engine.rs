@@ -1,13 +1,172 @@
+use crate::cursor::{self, CursorSink, MouseButton, SubpixelAccumulator};
+use crate::events::{EngineEvent, EventListener, GlideEndReason};
+use crate::geometry::{Float, Point, Rect, Vector};
+use crate::glide::{self, GlideEvent, GlideState, Transition};
+use crate::snap::{self, TargetProvider};
+use crate::spin;
 use crate::{config, utils::max};
-use cidre::cg::{Float, Point, Rect, Vector};
-use core_graphics::display;
-use objc2_app_kit::NSScreen;
+use std::f64::consts::{FRAC_1_SQRT_2, TAU};
+use std::fmt;
+use std::mem;
+use std::str::FromStr;
+use std::sync::mpsc;
 
 pub const ZERO_VECTOR: Vector = Vector { dx: 0.0, dy: 0.0 };
 
-enum VelocitySource {
+// A magnetic end that has not arrived by then gives up where it is
+const MAGNET_TIMEOUT: Float = 1.0;
+
+// A glide never turns faster than one revolution per second
+const MAX_ANGULAR_VELOCITY: Float = TAU;
+
+// What a glide does while a mouse button is held
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DragGlideMode {
+    // Warp the cursor, whatever is being dragged stays behind
+    Warp,
+    // Post drag events so the dragged window or selection follows the glide
+    Drag,
+    // Never glide during a drag
+    Off,
+}
+
+impl FromStr for DragGlideMode {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value {
+            "warp" => Ok(DragGlideMode::Warp),
+            "drag" => Ok(DragGlideMode::Drag),
+            "off" => Ok(DragGlideMode::Off),
+            _ => Err(format!("unknown drag glide mode `{}`", value)),
+        }
+    }
+}
+
+impl fmt::Display for DragGlideMode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            DragGlideMode::Warp => "warp",
+            DragGlideMode::Drag => "drag",
+            DragGlideMode::Off => "off",
+        };
+        f.write_str(name)
+    }
+}
+
+// How the pointer and trackpad velocity estimates combine into the release velocity
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum VelocityBlend {
+    // Whichever is faster
+    Max,
     Pointer,
     Trackpad,
+    // Fixed mix, `velocity_blend_weight` is the trackpad share
+    Weighted,
+    // Fixed mix while both agree, sliding toward the trackpad as they diverge
+    Confidence,
+}
+
+impl FromStr for VelocityBlend {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value {
+            "max" => Ok(VelocityBlend::Max),
+            "pointer" => Ok(VelocityBlend::Pointer),
+            "trackpad" => Ok(VelocityBlend::Trackpad),
+            "weighted" => Ok(VelocityBlend::Weighted),
+            "confidence" => Ok(VelocityBlend::Confidence),
+            _ => Err(format!("unknown velocity blend `{}`", value)),
+        }
+    }
+}
+
+impl fmt::Display for VelocityBlend {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            VelocityBlend::Max => "max",
+            VelocityBlend::Pointer => "pointer",
+            VelocityBlend::Trackpad => "trackpad",
+            VelocityBlend::Weighted => "weighted",
+            VelocityBlend::Confidence => "confidence",
+        };
+        f.write_str(name)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum VelocitySource {
+    Pointer,
+    Trackpad,
+    // Share of the trackpad velocity in the mix
+    Blend(Float),
+}
+
+impl fmt::Display for VelocitySource {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            VelocitySource::Pointer => f.write_str("pointer"),
+            VelocitySource::Trackpad => f.write_str("trackpad"),
+            VelocitySource::Blend(share) => write!(f, "blend({:.2} trackpad)", share),
+        }
+    }
+}
+
+// Combines the two velocity estimates. Without a trackpad estimate the pointer is all there is
+pub fn blend_velocity(
+    blend: VelocityBlend,
+    weight: Float,
+    pointer: Vector,
+    trackpad: Option<Vector>,
+) -> (Vector, VelocitySource) {
+    let Some(trackpad) = trackpad else {
+        return (pointer, VelocitySource::Pointer);
+    };
+    let pointer_speed = Engine::magnitude(&pointer);
+    let trackpad_speed = Engine::magnitude(&trackpad);
+    let share = match blend {
+        VelocityBlend::Max if trackpad_speed > pointer_speed => 1.0,
+        VelocityBlend::Max | VelocityBlend::Pointer => 0.0,
+        VelocityBlend::Trackpad => 1.0,
+        VelocityBlend::Weighted => weight.clamp(0.0, 1.0),
+        VelocityBlend::Confidence => {
+            // Agreement is 1 for identical vectors and falls with both the angle and the speed ratio
+            let faster = pointer_speed.max(trackpad_speed);
+            let agreement = if faster == 0.0 {
+                1.0
+            } else if pointer_speed.min(trackpad_speed) == 0.0 {
+                0.0
+            } else {
+                let cosine = (pointer.dx * trackpad.dx + pointer.dy * trackpad.dy)
+                    / (pointer_speed * trackpad_speed);
+                cosine.max(0.0) * pointer_speed.min(trackpad_speed) / faster
+            };
+            let weight = weight.clamp(0.0, 1.0);
+            agreement * weight + (1.0 - agreement)
+        }
+    };
+    let velocity = Vector {
+        dx: pointer.dx + (trackpad.dx - pointer.dx) * share,
+        dy: pointer.dy + (trackpad.dy - pointer.dy) * share,
+    };
+    let source = if share == 0.0 {
+        VelocitySource::Pointer
+    } else if share == 1.0 {
+        VelocitySource::Trackpad
+    } else {
+        VelocitySource::Blend(share)
+    };
+    (velocity, source)
+}
+
+// Phases of a glide once it has started
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum GlidePhase {
+    // Ramping from the pointer's own speed at lift-off up to the glide velocity, so a cursor
+    // that had already slowed down does not jump
+    Easing { from: Vector, elapsed: Float },
+    Coasting,
 }
 
 pub struct State {
@@ -15,7 +174,12 @@ pub struct State {
     previous_position: Point,
     last_input_delta: Vector,
     velocity: Vector,
-    pub is_gliding: bool,
+    // Velocity of the cursor itself on the last touch frame, before blending with the trackpad
+    pointer_velocity: Vector,
+    glide_phase: GlidePhase,
+    // Radians per second the velocity keeps turning during a curved glide
+    angular_velocity: Float,
+    glide: GlideState,
     velocity_source: VelocitySource,
 }
 
@@ -23,29 +187,208 @@ pub struct Engine {
     pub state: State,
     last_physical_mouse_position: Point,
     desktop_bounds: Rect,
+    cursor: Box<dyn CursorSink>,
+    subpixel: SubpixelAccumulator,
+    pressed_button: Option<MouseButton>,
+    targets: Option<Box<dyn TargetProvider>>,
+    // Target point of the magnetic end, looked up once per glide
+    magnet: Option<Point>,
+    magnet_checked: bool,
+    magnet_elapsed: Float,
+    // Normalized trackpad centroid, used as the raw input when an acceleration curve is set
+    touch_centroid: Option<Point>,
+    last_touch_centroid: Option<Point>,
+    // Fingers behind `touch_centroid`
+    touch_contacts: usize,
+    owns_pointer: bool,
+    listeners: Vec<EventListener>,
+    channels: Vec<mpsc::Sender<EngineEvent>>,
+    // Path length of the current glide
+    glide_distance: Float,
+    // Per-device multiplier on `trackpad_velocity_gain`
+    device_gain: Float,
+    // Touch velocity in mm/s, replaces the normalized velocity when set
+    physical_velocity: Option<Vector>,
+    // How firmly the fingers pressed before lift-off, scales the glide through `glide_press`
+    contact_weight: Float,
 }
 
 impl Engine {
     pub fn new() -> Self {
+        Self::with_cursor_sink(cursor::default_sink())
+    }
+
+    pub fn with_cursor_sink(cursor: Box<dyn CursorSink>) -> Self {
         Engine {
             state: State {
                 position: Point { x: 0.0, y: 0.0 },
                 previous_position: Point { x: 0.0, y: 0.0 },
                 last_input_delta: Vector { dx: 0.0, dy: 0.0 },
                 velocity: Vector { dx: 0.0, dy: 0.0 },
-                is_gliding: false,
+                pointer_velocity: Vector { dx: 0.0, dy: 0.0 },
+                glide_phase: GlidePhase::Coasting,
+                angular_velocity: 0.0,
+                glide: GlideState::Idle,
                 velocity_source: VelocitySource::Pointer,
             },
             last_physical_mouse_position: Point { x: 0.0, y: 0.0 },
             desktop_bounds: Rect::null(),
+            cursor,
+            subpixel: SubpixelAccumulator::new(),
+            pressed_button: None,
+            targets: snap::default_provider(),
+            magnet: None,
+            magnet_checked: false,
+            magnet_elapsed: 0.0,
+            touch_centroid: None,
+            last_touch_centroid: None,
+            touch_contacts: 0,
+            owns_pointer: false,
+            listeners: Vec::new(),
+            channels: Vec::new(),
+            glide_distance: 0.0,
+            device_gain: 1.0,
+            physical_velocity: None,
+            contact_weight: 0.0,
         }
     }
 
-    pub fn set_gliding(&mut self, value: bool) {
-        if self.state.is_gliding != value {
-            log::debug!("glide {}", if value { "start" } else { "stop" });
+    pub fn set_target_provider(&mut self, targets: Option<Box<dyn TargetProvider>>) {
+        self.targets = targets;
+    }
+
+    pub fn add_listener(&mut self, listener: EventListener) {
+        self.listeners.push(listener);
+    }
+
+    // Events for another thread. The sender is dropped once the receiver goes away
+    pub fn event_channel(&mut self) -> mpsc::Receiver<EngineEvent> {
+        let (sender, receiver) = mpsc::channel();
+        self.channels.push(sender);
+        receiver
+    }
+
+    fn emit(&mut self, event: EngineEvent) {
+        for listener in &mut self.listeners {
+            listener(&event);
+        }
+        self.channels.retain(|sender| sender.send(event).is_ok());
+    }
+
+    pub fn glide_state(&self) -> GlideState {
+        self.state.glide
+    }
+
+    pub fn is_gliding(&self) -> bool {
+        self.state.glide.is_gliding()
+    }
+
+    // Illegal events are logged and ignored, the state stays as it was
+    fn fire(&mut self, event: GlideEvent) {
+        let from = self.state.glide;
+        let to = match glide::transition(from, event) {
+            Ok(to) => to,
+            Err(error) => {
+                log::warn!("{}", error);
+                return;
+            }
+        };
+        let transition = Transition { from, event, to };
+        log::debug!("glide {}", transition);
+        if from.is_gliding() && !to.is_gliding() {
+            let reason = match event {
+                GlideEvent::TouchStart => GlideEndReason::Caught,
+                GlideEvent::Suspend => GlideEndReason::Suspended,
+                _ if self.glide_blocked_by_drag() => GlideEndReason::Dragging,
+                _ if self.magnet.is_some() && self.magnet_elapsed > MAGNET_TIMEOUT => {
+                    GlideEndReason::TimedOut
+                }
+                _ if self.magnet.is_some() => GlideEndReason::Settled,
+                _ => GlideEndReason::Stopped,
+            };
+            self.emit(EngineEvent::GlideEnd {
+                position: self.state.position,
+                reason,
+                distance: self.glide_distance,
+            });
+        }
+        if from.is_gliding() != to.is_gliding() {
+            self.magnet = None;
+            self.magnet_checked = false;
+            self.magnet_elapsed = 0.0;
+            self.state.glide_phase = GlidePhase::Coasting;
+        }
+        self.state.glide = to;
+        match event {
+            GlideEvent::TouchStart => self.emit(EngineEvent::TouchBegin {
+                position: self.state.position,
+            }),
+            GlideEvent::TouchEnd => self.emit(EngineEvent::TouchEnd {
+                position: self.state.position,
+                velocity: self.state.velocity,
+            }),
+            GlideEvent::Launch => {
+                self.glide_distance = 0.0;
+                self.emit(EngineEvent::GlideStart {
+                    position: self.state.position,
+                    velocity: self.state.velocity,
+                    source: self.state.velocity_source,
+                });
+            }
+            _ => {}
+        }
+        self.emit(EngineEvent::Transition(transition));
+    }
+
+    // Paused or suspended: any glide ends and the cursor is left to the OS until `resume`
+    pub fn suspend(&mut self) {
+        if self.state.glide != GlideState::Suppressed {
+            self.fire(GlideEvent::Suspend);
+        }
+        self.set_pointer_owned(false);
+    }
+
+    pub fn resume(&mut self) {
+        if self.state.glide == GlideState::Suppressed {
+            self.fire(GlideEvent::Resume);
         }
-        self.state.is_gliding = value;
+    }
+
+    pub fn position(&self) -> Point {
+        self.state.position
+    }
+
+    pub fn velocity(&self) -> Vector {
+        self.state.velocity
+    }
+
+    pub fn set_angular_velocity(&mut self, angular_velocity: Float) {
+        self.state.angular_velocity = angular_velocity;
+    }
+
+    pub fn set_device_gain(&mut self, gain: Float) {
+        self.device_gain = gain;
+    }
+
+    pub fn set_physical_velocity(&mut self, velocity: Option<Vector>) {
+        self.physical_velocity = velocity;
+    }
+
+    pub fn set_contact_weight(&mut self, weight: Float) {
+        self.contact_weight = weight;
+    }
+
+    pub fn set_touch_centroid(&mut self, centroid: Option<Point>, contacts: usize) {
+        // A finger put down or lifted moves the centroid without any finger moving
+        if contacts != self.touch_contacts {
+            self.last_touch_centroid = None;
+        }
+        self.touch_centroid = centroid;
+        self.touch_contacts = contacts;
+    }
+
+    pub fn set_pressed_button(&mut self, button: Option<MouseButton>) {
+        self.pressed_button = button;
     }
 
     pub fn begin_touch(&mut self, position: Point) {
@@ -53,7 +396,11 @@ impl Engine {
         self.state.previous_position = position;
         self.state.last_input_delta = ZERO_VECTOR;
         self.state.velocity = ZERO_VECTOR;
-        self.set_gliding(false);
+        self.state.angular_velocity = 0.0;
+        self.last_touch_centroid = None;
+        if self.state.glide != GlideState::Touching {
+            self.fire(GlideEvent::TouchStart);
+        }
     }
 
     pub fn handle_touch(
@@ -62,29 +409,60 @@ impl Engine {
         delta_time: Float,
         normalized_trackpad_velocity: Option<Vector>,
     ) {
-        let delta_pos = Point {
-            x: physical_position.x - self.last_physical_mouse_position.x,
-            y: physical_position.y - self.last_physical_mouse_position.y,
+        if self.state.glide != GlideState::Touching {
+            self.begin_touch(physical_position);
+        }
+        let config = config();
+        let curve = &config.acceleration_curve;
+        // With a curve Lapsus moves the cursor itself from raw trackpad motion, so the OS
+        // acceleration never stacks on top of it
+        let raw_delta = self.raw_touch_delta().filter(|_| !curve.is_off());
+        self.set_pointer_owned(raw_delta.is_some());
+        let mut delta_pos = match raw_delta {
+            Some(delta) => Point {
+                x: delta.dx,
+                y: delta.dy,
+            },
+            None => Point {
+                x: physical_position.x - self.last_physical_mouse_position.x,
+                y: physical_position.y - self.last_physical_mouse_position.y,
+            },
         };
         self.last_physical_mouse_position = physical_position;
         self.state.previous_position = self.state.position;
 
+        let mut trackpad_velocity = self.trackpad_velocity_in_pixels(normalized_trackpad_velocity);
+        if self.owns_pointer {
+            let gain = curve
+                .gain((delta_pos.x * delta_pos.x + delta_pos.y * delta_pos.y).sqrt() / delta_time);
+            delta_pos.x *= gain;
+            delta_pos.y *= gain;
+            trackpad_velocity = trackpad_velocity.map(|velocity| {
+                let gain = curve.gain(Self::magnitude(&velocity));
+                Vector {
+                    dx: velocity.dx * gain,
+                    dy: velocity.dy * gain,
+                }
+            });
+        }
+
         let pointer_velocity = Vector {
             dx: delta_pos.x / delta_time,
             dy: delta_pos.y / delta_time,
         };
 
-        let mut velocity = pointer_velocity;
-        let mut source: VelocitySource = VelocitySource::Pointer;
-        let trackpad_velocity = self.trackpad_velocity_in_pixels(normalized_trackpad_velocity);
-        if let Some(trackpad_velocity) = trackpad_velocity {
-            if Self::magnitude(&trackpad_velocity) > Self::magnitude(&pointer_velocity) {
-                velocity = trackpad_velocity;
-                source = VelocitySource::Trackpad;
-            }
+        let (velocity, source) = blend_velocity(
+            config.velocity_blend,
+            config.velocity_blend_weight,
+            pointer_velocity,
+            trackpad_velocity,
+        );
+        if mem::discriminant(&source) != mem::discriminant(&self.state.velocity_source) {
+            log::debug!("velocity source: {}", source);
         }
         self.state.velocity = velocity;
         self.state.velocity_source = source;
+        self.state.pointer_velocity = pointer_velocity;
         self.state.position.x += delta_pos.x;
         self.state.position.y += delta_pos.y;
         self.state.last_input_delta = Vector {
@@ -92,8 +470,56 @@ impl Engine {
             dy: delta_pos.y,
         };
 
-        if self.state.is_gliding {
-            self.set_gliding(false);
+        if self.owns_pointer {
+            let bounds = self.desktop_bounds;
+            self.state.position.x = self
+                .state
+                .position
+                .x
+                .clamp(bounds.origin.x, bounds.origin.x + bounds.size.width - 1.0);
+            self.state.position.y = self
+                .state
+                .position
+                .y
+                .clamp(bounds.origin.y, bounds.origin.y + bounds.size.height - 1.0);
+            let step = self.subpixel.step(self.state.last_input_delta);
+            // The finger stands in for the OS here, so a held button drags whatever the glide
+            // mode, which only decides what a glide does
+            match self.pressed_button {
+                Some(button) => self.cursor.drag_cursor(self.state.position, step, button),
+                None => self.cursor.move_cursor(self.state.position, step),
+            }
+            self.last_physical_mouse_position = self.state.position;
+        }
+    }
+
+    // Centroid motion since the previous touch frame, in desktop pixels before any acceleration.
+    // Only one finger points, more are a scroll or a gesture and leave the cursor to the OS
+    fn raw_touch_delta(&mut self) -> Option<Vector> {
+        if self.touch_contacts != 1 {
+            return None;
+        }
+        let centroid = self.touch_centroid?;
+        let previous = self
+            .last_touch_centroid
+            .replace(centroid)
+            .unwrap_or(centroid);
+        if self.desktop_bounds == Rect::null() {
+            return None;
+        }
+        let gain = config().trackpad_velocity_gain * self.device_gain;
+        Some(Vector {
+            dx: (centroid.x - previous.x) * self.desktop_bounds.size.width * gain,
+            dy: (centroid.y - previous.y) * self.desktop_bounds.size.height * gain,
+        })
+    }
+
+    fn set_pointer_owned(&mut self, owned: bool) {
+        if self.owns_pointer != owned {
+            log::debug!("pointer {}", if owned { "owned" } else { "released" });
+            self.owns_pointer = owned;
+            self.subpixel.reset();
+            self.cursor.set_pointer_owned(owned);
         }
     }
 
@@ -102,73 +528,249 @@ impl Engine {
         physical_position: Point,
         delta_time: Float,
         suppress_glide: bool,
-        touch_ended_recently: bool,
     ) {
+        self.set_pointer_owned(false);
         self.last_physical_mouse_position = physical_position;
-        if touch_ended_recently {
+        if self.state.glide == GlideState::Touching {
+            self.fire(GlideEvent::TouchEnd);
             if suppress_glide {
-                self.set_gliding(false);
+                log::debug!("glide suppressed: multi-finger gesture");
+                self.fire(GlideEvent::Stop);
             } else {
                 self.begin_glide_if_needed();
             }
         }
 
-        if self.state.is_gliding {
+        if self.is_gliding() && delta_time > 0.0 {
             self.apply_momentum(delta_time);
-        } else {
+        } else if !self.is_gliding() {
             self.state.last_input_delta = ZERO_VECTOR;
         }
     }
 
+    // Runs up to `steps` fixed glide steps, fewer when the glide ends on the way
+    pub fn step_glide(&mut self, steps: u32, step: Float) {
+        for _ in 0..steps {
+            if !self.is_gliding() {
+                break;
+            }
+            self.apply_momentum(step);
+        }
+    }
+
     fn begin_glide_if_needed(&mut self) {
-        let speed = Self::magnitude(&self.state.velocity);
         let min_speed = config().minimum_glide_velocity;
+        if self.glide_blocked_by_drag() {
+            log::debug!("glide suppressed: dragging");
+            self.fire(GlideEvent::Stop);
+            self.state.velocity = ZERO_VECTOR;
+            return;
+        }
+        // A light flick glides further, a press damps the glide or cancels it below `min_speed`
+        let strength = config().glide_press.strength(self.contact_weight);
+        if strength != 1.0 {
+            log::debug!(
+                "glide strength {:.2} at contact weight {:.2}",
+                strength,
+                self.contact_weight
+            );
+            self.state.velocity = Self::clamped_velocity(
+                &Vector {
+                    dx: self.state.velocity.dx * strength,
+                    dy: self.state.velocity.dy * strength,
+                },
+                config().maximum_momentum_speed,
+            );
+        }
+        // Locked before the launch check, which has to see the speed the glide would run at
+        let config = config();
+        let velocity = self.state.velocity;
+        if config.axis_lock_degrees > 0.0 {
+            self.state.velocity =
+                lock_direction(velocity, config.axis_lock_degrees, config.snap_diagonals);
+        }
+        let locked = self.state.velocity != velocity;
+        let speed = Self::magnitude(&self.state.velocity);
         if speed < min_speed {
             log::debug!(
                 "glide suppressed: speed {:.3} < min {:.3}",
                 speed,
                 min_speed
             );
-            self.set_gliding(false);
+            self.fire(GlideEvent::Stop);
             self.state.velocity = ZERO_VECTOR;
             return;
         } else {
-            log::debug!("glide start: speed {:.3} >= min {:.3}", speed, min_speed);
-            self.set_gliding(true);
-            self.update_cursor_position_on_screen();
+            log::debug!(
+                "glide start: speed {:.3} >= min {:.3}, from {}",
+                speed,
+                min_speed,
+                self.state.velocity_source
+            );
+            // A locked glide runs straight
+            self.state.angular_velocity = if config.curved_glides && !locked {
+                (self.state.angular_velocity * config.spin_gain)
+                    .clamp(-MAX_ANGULAR_VELOCITY, MAX_ANGULAR_VELOCITY)
+            } else {
+                0.0
+            };
+            self.fire(GlideEvent::Launch);
+            if config.glide_ease_time > 0.0 {
+                self.state.glide_phase = GlidePhase::Easing {
+                    from: self.ease_start(),
+                    elapsed: 0.0,
+                };
+            }
+            self.subpixel.reset();
+            self.update_cursor_position_on_screen(ZERO_VECTOR);
         }
     }
 
     pub fn apply_momentum(&mut self, delta_time: Float) {
         let config = config();
+        // A button pressed mid-glide starts a drag, which this mode never glides through
+        if self.glide_blocked_by_drag() {
+            log::debug!("glide stopped: drag started");
+            self.fire(GlideEvent::Stop);
+            self.state.velocity = ZERO_VECTOR;
+            return;
+        }
         let decay_factor = max(0.0, 1.0 - config.glide_decay_per_second * delta_time);
         self.state.velocity.dx *= decay_factor;
         self.state.velocity.dy *= decay_factor;
 
+        if self.state.angular_velocity != 0.0 {
+            self.state.velocity = spin::rotate(
+                self.state.velocity,
+                self.state.angular_velocity * delta_time,
+            );
+            self.state.angular_velocity *=
+                max(0.0, 1.0 - config.spin_decay_per_second * delta_time);
+        }
+
+        if let Some(target) = self.magnet_target() {
+            self.state.velocity = snap::attract(
+                self.state.position,
+                self.state.velocity,
+                target,
+                config.magnetic_strength,
+                delta_time,
+            );
+        }
+
+        let velocity = self.eased_velocity(delta_time);
         let momentum_delta = Vector {
-            dx: self.state.velocity.dx * delta_time,
-            dy: self.state.velocity.dy * delta_time,
+            dx: velocity.dx * delta_time,
+            dy: velocity.dy * delta_time,
         };
 
         self.state.previous_position = self.state.position;
         self.state.position.x += momentum_delta.dx;
         self.state.position.y += momentum_delta.dy;
         self.state.last_input_delta = momentum_delta;
+        self.glide_distance += Self::magnitude(&momentum_delta);
 
-        self.update_cursor_position_on_screen();
+        // Relative outputs only move in whole pixels, so carry the fraction over to the next tick
+        let step = self.subpixel.step(momentum_delta);
+        self.update_cursor_position_on_screen(step);
+        self.emit(EngineEvent::GlideTick {
+            position: self.state.position,
+            velocity,
+            delta: momentum_delta,
+        });
 
         let speed = Self::magnitude(&self.state.velocity);
-        if speed < config.minimum_glide_velocity * config.glide_stop_speed_factor {
-            self.set_gliding(false);
+        let stopped = match self.magnet {
+            Some(target) => {
+                self.magnet_elapsed += delta_time;
+                snap::distance(self.state.position, target) < 0.5
+                    || self.magnet_elapsed > MAGNET_TIMEOUT
+            }
+            None => speed < config.minimum_glide_velocity * config.glide_stop_speed_factor,
+        };
+        // Too slow to have started a glide, or settling onto a target
+        let braking = speed < config.minimum_glide_velocity || self.magnet.is_some();
+        if braking && !stopped && self.state.glide == GlideState::Gliding {
+            self.fire(GlideEvent::Brake);
+        }
+        if stopped {
+            self.fire(GlideEvent::Stop);
             self.state.velocity = ZERO_VECTOR;
-            self.update_cursor_position_on_screen();
+            self.update_cursor_position_on_screen(ZERO_VECTOR);
+        }
+    }
+
+    // The pointer velocity at lift-off along the glide direction. Only its speed carries over, so
+    // the ramp never bends the glide or undoes an axis lock
+    fn ease_start(&self) -> Vector {
+        let velocity = self.state.velocity;
+        let speed = Self::magnitude(&velocity);
+        if speed == 0.0 {
+            return ZERO_VECTOR;
+        }
+        let pointer = self.state.pointer_velocity;
+        let along =
+            ((pointer.dx * velocity.dx + pointer.dy * velocity.dy) / speed).clamp(0.0, speed);
+        Vector {
+            dx: velocity.dx / speed * along,
+            dy: velocity.dy / speed * along,
+        }
+    }
+
+    // Velocity to move with this tick: the glide velocity, blended in with a smoothstep while easing
+    fn eased_velocity(&mut self, delta_time: Float) -> Vector {
+        let GlidePhase::Easing { from, elapsed } = self.state.glide_phase else {
+            return self.state.velocity;
+        };
+        let duration = config().glide_ease_time;
+        let elapsed = elapsed + delta_time;
+        if elapsed >= duration {
+            log::debug!("glide eased in after {:.3}s", elapsed);
+            self.state.glide_phase = GlidePhase::Coasting;
+            return self.state.velocity;
+        }
+        self.state.glide_phase = GlidePhase::Easing { from, elapsed };
+        let t = elapsed / duration;
+        let t = t * t * (3.0 - 2.0 * t);
+        Vector {
+            dx: from.dx + (self.state.velocity.dx - from.dx) * t,
+            dy: from.dy + (self.state.velocity.dy - from.dy) * t,
+        }
+    }
+
+    // Once the glide is slow enough, look for a target around where it would come to rest
+    fn magnet_target(&mut self) -> Option<Point> {
+        let config = config();
+        if self.magnet_checked || !config.magnetic_end {
+            return self.magnet;
+        }
+        if Self::magnitude(&self.state.velocity) >= config.magnetic_speed {
+            return None;
         }
+        self.magnet_checked = true;
+        let targets = self.targets.as_mut()?;
+        // Exponential decay covers velocity / rate before stopping
+        let rate = config.glide_decay_per_second.max(1.0);
+        let rest = Point {
+            x: self.state.position.x + self.state.velocity.dx / rate,
+            y: self.state.position.y + self.state.velocity.dy / rate,
+        };
+        let candidates = targets.targets_near(rest, config.magnetic_radius);
+        self.magnet = snap::snap_point(rest, &candidates, config.magnetic_radius);
+        if let Some(target) = self.magnet {
+            log::debug!("magnetic end toward ({:.1},{:.1})", target.x, target.y);
+        }
+        self.magnet
     }
 
     fn magnitude(vector: &Vector) -> Float {
         (vector.dx * vector.dx + vector.dy * vector.dy).sqrt()
     }
 
+    pub fn desktop_bounds(&self) -> Rect {
+        self.desktop_bounds
+    }
+
     pub fn update_desktop_bounds(&mut self, bounds: Rect) {
         self.desktop_bounds = bounds;
         log::debug!(
@@ -181,26 +783,21 @@ impl Engine {
     }
 
     // Advance the cursor position based on the current momentum
-    pub fn update_cursor_position_on_screen(&mut self) {
-        let target = self.state.position;
-        let mtm = objc2_foundation::MainThreadMarker::new().expect("must be on the main thread");
-        if let Some(screen) = NSScreen::mainScreen(mtm) {
-            let local_x = target.x - screen.frame().min().x;
-            let local_y_from_bottom = target.y - screen.frame().min().y;
-            let local_y = screen.frame().size.height - local_y_from_bottom;
-            let _error = display::CGDisplay::move_cursor_to_point(
-                &display::CGDisplay::main(),
-                display::CGPoint {
-                    x: local_x,
-                    y: local_y,
-                },
-            );
-        } else {
-            return;
+    pub fn update_cursor_position_on_screen(&mut self, delta: Vector) {
+        match (self.pressed_button, config().drag_glide_mode) {
+            (Some(button), DragGlideMode::Drag) => {
+                self.cursor.drag_cursor(self.state.position, delta, button)
+            }
+            _ => self.cursor.move_cursor(self.state.position, delta),
         }
     }
 
+    fn glide_blocked_by_drag(&self) -> bool {
+        self.pressed_button.is_some() && config().drag_glide_mode == DragGlideMode::Off
+    }
+
     pub fn update_engine_state(&mut self, physical_position: Point) {
+        self.set_pointer_owned(false);
         self.state.position = physical_position;
         self.state.previous_position = physical_position;
         self.state.last_input_delta = ZERO_VECTOR;
@@ -216,13 +813,19 @@ impl Engine {
             return None;
         }
         if let Some(normalized_velocity) = normalized_velocity {
-            let scaled = Vector {
-                dx: normalized_velocity.dx
-                    * self.desktop_bounds.size.width
-                    * config.trackpad_velocity_gain,
-                dy: normalized_velocity.dy
-                    * self.desktop_bounds.size.height
-                    * config.trackpad_velocity_gain,
+            let scaled = if let Some(physical) = self.physical_velocity {
+                // Independent of the trackpad and desktop size
+                let gain = config.pixels_per_mm * self.device_gain;
+                Vector {
+                    dx: physical.dx * gain,
+                    dy: physical.dy * gain,
+                }
+            } else {
+                let gain = config.trackpad_velocity_gain * self.device_gain;
+                Vector {
+                    dx: normalized_velocity.dx * self.desktop_bounds.size.width * gain,
+                    dy: normalized_velocity.dy * self.desktop_bounds.size.height * gain,
+                }
             };
             return Some(Self::clamped_velocity(
                 &scaled,
@@ -246,3 +849,37 @@ impl Engine {
         }
     }
 }
+
+// Unit vectors of the eight snapping directions, counter-clockwise from +x
+const DIRECTIONS: [(Float, Float); 8] = [
+    (1.0, 0.0),
+    (FRAC_1_SQRT_2, FRAC_1_SQRT_2),
+    (0.0, 1.0),
+    (-FRAC_1_SQRT_2, FRAC_1_SQRT_2),
+    (-1.0, 0.0),
+    (-FRAC_1_SQRT_2, -FRAC_1_SQRT_2),
+    (0.0, -1.0),
+    (FRAC_1_SQRT_2, -FRAC_1_SQRT_2),
+];
+
+// Projects a release velocity within `tolerance_degrees` of an axis (or of a diagonal too when
+// `diagonals` is set) onto that direction, so the glide runs perfectly straight
+pub fn lock_direction(velocity: Vector, tolerance_degrees: Float, diagonals: bool) -> Vector {
+    if velocity.dx == 0.0 && velocity.dy == 0.0 {
+        return velocity;
+    }
+    let step = if diagonals { 45.0 } else { 90.0 };
+    let angle = velocity.dy.atan2(velocity.dx).to_degrees();
+    let sector = (angle / step).round();
+    if (angle - sector * step).abs() > tolerance_degrees {
+        return velocity;
+    }
+    let index = (sector * step / 45.0) as i32;
+    let (x, y) = DIRECTIONS[index.rem_euclid(8) as usize];
+    let along = velocity.dx * x + velocity.dy * y;
+    log::debug!("glide direction locked to {:.0} degrees", sector * step);
+    Vector {
+        dx: along * x,
+        dy: along * y,
+    }
+}
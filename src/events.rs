@@ -0,0 +1,53 @@
+// Structured lifecycle events from the engine, for feedback, overlays and analytics. Subscribe
+// with `Engine::add_listener` or `Engine::event_channel`
+
+use crate::engine::VelocitySource;
+use crate::geometry::{Float, Point, Vector};
+use crate::glide::Transition;
+
+pub type EventListener = Box<dyn FnMut(&EngineEvent)>;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GlideEndReason {
+    // Slowed down to the stop speed
+    Stopped,
+    // A magnetic end reached its target
+    Settled,
+    // A magnetic end gave up before reaching its target
+    TimedOut,
+    // A finger landed on the trackpad
+    Caught,
+    // A button went down and drags do not glide
+    Dragging,
+    Suspended,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum EngineEvent {
+    TouchBegin {
+        position: Point,
+    },
+    TouchEnd {
+        position: Point,
+        velocity: Vector,
+    },
+    GlideStart {
+        position: Point,
+        velocity: Vector,
+        source: VelocitySource,
+    },
+    // Every glide step, `delta` is the motion of this tick
+    GlideTick {
+        position: Point,
+        velocity: Vector,
+        delta: Vector,
+    },
+    GlideEnd {
+        position: Point,
+        reason: GlideEndReason,
+        // Length of the path covered since the glide started
+        distance: Float,
+    },
+    // Every step of the glide state machine, after the event it caused
+    Transition(Transition),
+}
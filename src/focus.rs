@@ -0,0 +1,214 @@
+// Per-application rules: pick a profile or turn gliding off depending on the frontmost app.
+// Rules live in the `app_rules` file of the support directory, one per line:
+//
+//     <priority> bundle:<identifier> disable
+//     <priority> exe:<executable name> profile <name>
+//
+// A trailing `*` in the pattern matches by prefix. The highest priority match wins, ties go to
+// the rule listed first
+
+use crate::profile;
+use std::cmp::Reverse;
+use std::fs;
+use std::io;
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct FocusedApp {
+    pub bundle_id: Option<String>,
+    pub executable: Option<String>,
+}
+
+pub trait FocusProvider {
+    fn frontmost(&self) -> Option<FocusedApp>;
+}
+
+// macOS: ask the shared workspace
+#[cfg(target_os = "macos")]
+pub struct WorkspaceFocusProvider;
+
+#[cfg(target_os = "macos")]
+impl FocusProvider for WorkspaceFocusProvider {
+    fn frontmost(&self) -> Option<FocusedApp> {
+        let app = objc2_app_kit::NSWorkspace::sharedWorkspace().frontmostApplication()?;
+        let bundle_id = app.bundleIdentifier().map(|id| id.to_string());
+        let executable = app
+            .executableURL()
+            .and_then(|url| url.lastPathComponent())
+            .map(|name| name.to_string());
+        Some(FocusedApp {
+            bundle_id,
+            executable,
+        })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum AppMatcher {
+    BundleId(String),
+    Executable(String),
+}
+
+impl AppMatcher {
+    pub fn matches(&self, app: &FocusedApp) -> bool {
+        match self {
+            AppMatcher::BundleId(pattern) => app
+                .bundle_id
+                .as_deref()
+                .is_some_and(|id| pattern_matches(pattern, id)),
+            AppMatcher::Executable(pattern) => app
+                .executable
+                .as_deref()
+                .is_some_and(|name| pattern_matches(pattern, name)),
+        }
+    }
+}
+
+fn pattern_matches(pattern: &str, value: &str) -> bool {
+    let pattern = pattern.to_ascii_lowercase();
+    let value = value.to_ascii_lowercase();
+    match pattern.strip_suffix('*') {
+        Some(prefix) => value.starts_with(prefix),
+        None => value == pattern,
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum AppAction {
+    Disable,
+    Profile(String),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AppRule {
+    pub priority: i32,
+    pub matcher: AppMatcher,
+    pub action: AppAction,
+}
+
+impl AppRule {
+    pub fn parse(line: &str) -> Result<Self, String> {
+        let fields: Vec<&str> = line.split_whitespace().collect();
+        let (priority, target, action) = match fields.as_slice() {
+            [priority, target, action @ ..] => (priority, target, action),
+            _ => {
+                return Err(format!(
+                    "expected `<priority> <match> <action>` in `{}`",
+                    line
+                ));
+            }
+        };
+        let priority = priority
+            .parse::<i32>()
+            .map_err(|_| format!("invalid priority `{}`", priority))?;
+        let matcher = if let Some(id) = target.strip_prefix("bundle:") {
+            AppMatcher::BundleId(id.to_string())
+        } else if let Some(name) = target.strip_prefix("exe:") {
+            AppMatcher::Executable(name.to_string())
+        } else {
+            return Err(format!(
+                "expected bundle:<id> or exe:<name>, got `{}`",
+                target
+            ));
+        };
+        let action = match action {
+            ["disable"] => AppAction::Disable,
+            ["profile", name] => AppAction::Profile(name.to_string()),
+            _ => {
+                return Err(format!(
+                    "expected `disable` or `profile <name>` in `{}`",
+                    line
+                ));
+            }
+        };
+        Ok(Self {
+            priority,
+            matcher,
+            action,
+        })
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct AppRules {
+    rules: Vec<AppRule>,
+}
+
+impl AppRules {
+    pub fn new(mut rules: Vec<AppRule>) -> Self {
+        // Stable, so equal priorities keep their listed order
+        rules.sort_by_key(|rule| Reverse(rule.priority));
+        Self { rules }
+    }
+
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let mut rules = Vec::new();
+        for (index, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            rules.push(
+                AppRule::parse(line).map_err(|error| format!("line {}: {}", index + 1, error))?,
+            );
+        }
+        Ok(Self::new(rules))
+    }
+
+    pub fn load() -> Result<Self, String> {
+        let path = profile::support_dir().join("app_rules");
+        match fs::read_to_string(&path) {
+            Ok(text) => {
+                Self::parse(&text).map_err(|error| format!("{}: {}", path.display(), error))
+            }
+            Err(error) if error.kind() == io::ErrorKind::NotFound => Ok(Self::default()),
+            Err(error) => Err(format!("{}: {}", path.display(), error)),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.rules.is_empty()
+    }
+
+    pub fn evaluate(&self, app: &FocusedApp) -> Option<&AppRule> {
+        self.rules.iter().find(|rule| rule.matcher.matches(app))
+    }
+}
+
+// Polls the focus provider at a fixed interval and reports when the effective action changes
+#[derive(Debug, Default)]
+pub struct FocusTracker {
+    rules: AppRules,
+    next_check: f64,
+    action: Option<AppAction>,
+}
+
+impl FocusTracker {
+    pub fn new(rules: AppRules) -> Self {
+        Self {
+            rules,
+            next_check: 0.0,
+            action: None,
+        }
+    }
+
+    pub fn action(&self) -> Option<&AppAction> {
+        self.action.as_ref()
+    }
+
+    // Returns true when the action for the frontmost app differs from the previous poll
+    pub fn poll(&mut self, provider: &dyn FocusProvider, now: f64, interval: f64) -> bool {
+        if self.rules.is_empty() || now < self.next_check {
+            return false;
+        }
+        self.next_check = now + interval;
+        let action = provider
+            .frontmost()
+            .and_then(|app| self.rules.evaluate(&app))
+            .map(|rule| rule.action.clone());
+        if action == self.action {
+            return false;
+        }
+        self.action = action;
+        true
+    }
+}
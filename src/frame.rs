@@ -0,0 +1,86 @@
+use crate::geometry::{Float, Point};
+use std::fmt::Write;
+
+// One finger as reported by a contact frame, position is normalized to the trackpad surface (0..1)
+// Size and pressure are in the units of the device, zero when it does not report them
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Contact {
+    pub position: Point,
+    pub size: Float,
+    pub pressure: Float,
+}
+
+impl Contact {
+    pub fn at(position: Point) -> Self {
+        Self {
+            position,
+            size: 0.0,
+            pressure: 0.0,
+        }
+    }
+}
+
+// Everything the multitouch callback saw at one instant
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct ContactFrame {
+    pub timestamp: f64,
+    pub contacts: Vec<Contact>,
+}
+
+impl ContactFrame {
+    pub fn positions(&self) -> Vec<Point> {
+        self.contacts
+            .iter()
+            .map(|contact| contact.position)
+            .collect()
+    }
+
+    // Text form used by traces: `<timestamp> <x>,<y>[,<size>,<pressure>] ...`
+    pub fn to_line(&self) -> String {
+        format_line(self.timestamp, &self.contacts)
+    }
+
+    pub fn parse_line(line: &str) -> Result<Self, String> {
+        let mut fields = line.split_whitespace();
+        let timestamp = fields
+            .next()
+            .ok_or_else(|| "empty frame".to_string())?
+            .parse::<f64>()
+            .map_err(|_| format!("invalid timestamp in `{}`", line))?;
+        let mut contacts = Vec::new();
+        for field in fields {
+            let values = field
+                .split(',')
+                .map(|value| value.parse::<Float>())
+                .collect::<Result<Vec<_>, _>>()
+                .map_err(|_| format!("invalid contact `{}`", field))?;
+            // Traces recorded before size and pressure have positions only
+            let contact = match values[..] {
+                [x, y] => Contact::at(Point { x, y }),
+                [x, y, size, pressure] => Contact {
+                    position: Point { x, y },
+                    size,
+                    pressure,
+                },
+                _ => return Err(format!("invalid contact `{}`", field)),
+            };
+            contacts.push(contact);
+        }
+        Ok(Self {
+            timestamp,
+            contacts,
+        })
+    }
+}
+
+// Lets the frame callback write a line straight from its contact buffer
+pub fn format_line(timestamp: f64, contacts: &[Contact]) -> String {
+    let mut line = format!("{:.6}", timestamp);
+    for contact in contacts {
+        let _ = write!(line, " {:.5},{:.5}", contact.position.x, contact.position.y);
+        if contact.size != 0.0 || contact.pressure != 0.0 {
+            let _ = write!(line, ",{:.4},{:.4}", contact.size, contact.pressure);
+        }
+    }
+    line
+}
@@ -0,0 +1,44 @@
+// Plain desktop geometry, laid out like CoreGraphics' CGPoint, CGVector, CGSize and CGRect so the
+// platform code converts field by field and everything else builds on any OS
+
+pub type Float = f64;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Point {
+    pub x: Float,
+    pub y: Float,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Vector {
+    pub dx: Float,
+    pub dy: Float,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Size {
+    pub width: Float,
+    pub height: Float,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Rect {
+    pub origin: Point,
+    pub size: Size,
+}
+
+impl Rect {
+    // No rectangle at all, like CGRectNull: a union with it is the other rectangle
+    pub fn null() -> Self {
+        Rect {
+            origin: Point {
+                x: Float::INFINITY,
+                y: Float::INFINITY,
+            },
+            size: Size {
+                width: 0.0,
+                height: 0.0,
+            },
+        }
+    }
+}
@@ -0,0 +1,113 @@
+// The glide state machine. Every change goes through `transition`, so the logged sequence of
+// transitions explains why the cursor did or did not glide
+
+use std::fmt;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GlideState {
+    Idle,
+    Touching,
+    // Fingers just lifted, deciding whether the release is a flick
+    Releasing,
+    Gliding,
+    // Final, slow part of a glide, including a magnetic end
+    Braking,
+    // Paused or suspended, the cursor belongs to the OS
+    Suppressed,
+}
+
+impl GlideState {
+    pub fn is_gliding(self) -> bool {
+        matches!(self, GlideState::Gliding | GlideState::Braking)
+    }
+}
+
+impl fmt::Display for GlideState {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            GlideState::Idle => "idle",
+            GlideState::Touching => "touching",
+            GlideState::Releasing => "releasing",
+            GlideState::Gliding => "gliding",
+            GlideState::Braking => "braking",
+            GlideState::Suppressed => "suppressed",
+        };
+        f.write_str(name)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GlideEvent {
+    TouchStart,
+    TouchEnd,
+    // The release was fast enough to glide
+    Launch,
+    // The glide slowed into its final phase
+    Brake,
+    // The release did not glide, or the glide ran out or was cut off
+    Stop,
+    Suspend,
+    Resume,
+}
+
+impl fmt::Display for GlideEvent {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            GlideEvent::TouchStart => "touch_start",
+            GlideEvent::TouchEnd => "touch_end",
+            GlideEvent::Launch => "launch",
+            GlideEvent::Brake => "brake",
+            GlideEvent::Stop => "stop",
+            GlideEvent::Suspend => "suspend",
+            GlideEvent::Resume => "resume",
+        };
+        f.write_str(name)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Transition {
+    pub from: GlideState,
+    pub event: GlideEvent,
+    pub to: GlideState,
+}
+
+impl fmt::Display for Transition {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} --{}--> {}", self.from, self.event, self.to)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct IllegalTransition {
+    pub state: GlideState,
+    pub event: GlideEvent,
+}
+
+impl fmt::Display for IllegalTransition {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "illegal glide transition: {} while {}",
+            self.event, self.state
+        )
+    }
+}
+
+pub fn transition(state: GlideState, event: GlideEvent) -> Result<GlideState, IllegalTransition> {
+    use GlideEvent::*;
+    use GlideState::*;
+    let next = match (state, event) {
+        (Suppressed, Resume) => Idle,
+        (Suppressed, _) => return Err(IllegalTransition { state, event }),
+        (_, Suspend) => Suppressed,
+        // A finger landing on a glide catches it
+        (Idle | Gliding | Braking, TouchStart) => Touching,
+        (Touching, TouchEnd) => Releasing,
+        (Releasing, Launch) => Gliding,
+        (Gliding, Brake) => Braking,
+        (Releasing | Gliding | Braking, Stop) => Idle,
+        _ => return Err(IllegalTransition { state, event }),
+    };
+    Ok(next)
+}
@@ -0,0 +1,150 @@
+#[cfg(target_os = "macos")]
+pub mod app;
+pub mod calibrate;
+pub mod clock;
+pub mod control;
+#[cfg(target_os = "macos")]
+pub mod controller;
+pub mod cursor;
+pub mod curve;
+pub mod device;
+pub mod engine;
+pub mod events;
+pub mod focus;
+pub mod glide;
+pub mod frame;
+pub mod geometry;
+pub mod piecewise;
+pub mod press;
+pub mod profile;
+pub mod protocol;
+pub mod scheduler;
+pub mod simulation;
+pub mod snap;
+pub mod snapshot;
+pub mod spin;
+pub mod suspend;
+pub mod sweep;
+pub mod tests;
+pub mod trace;
+pub mod trackpad;
+pub mod uinput;
+pub mod utils;
+
+use geometry::Float;
+use std::cell::RefCell;
+use std::sync::{Arc, OnceLock, RwLock};
+
+// Declares every tunable once: the struct field, the build-time env default and the string
+// accessors used by the control socket and profiles
+macro_rules! config {
+    ($($field:ident: $ty:ty = $env:literal,)*) => {
+        #[derive(Clone, Debug)]
+        pub struct Config {
+            $($field: $ty,)*
+        }
+
+        impl Config {
+            pub const KEYS: &'static [&'static str] = &[$(stringify!($field)),*];
+
+            pub fn defaults() -> Self {
+                Self {
+                    $($field: env!($env).parse::<$ty>().unwrap(),)*
+                }
+            }
+
+            pub fn get(&self, key: &str) -> Option<String> {
+                match key {
+                    $(stringify!($field) => Some(self.$field.to_string()),)*
+                    _ => None,
+                }
+            }
+
+            pub fn set(&mut self, key: &str, value: &str) -> Result<(), String> {
+                match key {
+                    $(stringify!($field) => {
+                        self.$field = value
+                            .parse::<$ty>()
+                            .map_err(|_| format!("invalid value `{}` for {}", value, key))?;
+                        Ok(())
+                    })*
+                    _ => Err(format!("unknown config key `{}`", key)),
+                }
+            }
+        }
+    };
+}
+
+config! {
+    maximum_momentum_speed: Float = "MAXIMUM_MOMENTUM_SPEED",
+    trackpad_velocity_gain: Float = "TRACKPAD_VELOCITY_GAIN",
+    glide_decay_per_second: Float = "GLIDE_DECAY_PER_SECOND",
+    minimum_glide_velocity: Float = "MINIMUM_GLIDE_VELOCITY",
+    glide_stop_speed_factor: Float = "GLIDE_STOP_SPEED_FACTOR",
+    velocity_smoothing: Float = "VELOCITY_SMOOTHING",
+    min_dt: Float = "MIN_DT",
+    multi_finger_suppression_deadline: f64 = "MULTI_FINGER_SUPPRESSION_DEADLINE",
+    suspend_while_dragging: bool = "SUSPEND_WHILE_DRAGGING",
+    suspend_with_external_mouse: bool = "SUSPEND_WITH_EXTERNAL_MOUSE",
+    external_mouse_hold: f64 = "EXTERNAL_MOUSE_HOLD",
+    suspend_modifier: suspend::Modifier = "SUSPEND_MODIFIER",
+    drag_glide_mode: engine::DragGlideMode = "DRAG_GLIDE_MODE",
+    app_rule_interval: f64 = "APP_RULE_INTERVAL",
+    magnetic_end: bool = "MAGNETIC_END",
+    magnetic_speed: Float = "MAGNETIC_SPEED",
+    magnetic_radius: Float = "MAGNETIC_RADIUS",
+    magnetic_strength: Float = "MAGNETIC_STRENGTH",
+    axis_lock_degrees: Float = "AXIS_LOCK_DEGREES",
+    snap_diagonals: bool = "SNAP_DIAGONALS",
+    curved_glides: bool = "CURVED_GLIDES",
+    spin_window: f64 = "SPIN_WINDOW",
+    spin_gain: Float = "SPIN_GAIN",
+    spin_decay_per_second: Float = "SPIN_DECAY_PER_SECOND",
+    acceleration_curve: curve::AccelerationCurve = "ACCELERATION_CURVE",
+    velocity_blend: engine::VelocityBlend = "VELOCITY_BLEND",
+    velocity_blend_weight: Float = "VELOCITY_BLEND_WEIGHT",
+    glide_ease_time: Float = "GLIDE_EASE_TIME",
+    active_tick_interval: f64 = "ACTIVE_TICK_INTERVAL",
+    idle_tick_interval: f64 = "IDLE_TICK_INTERVAL",
+    idle_delay: f64 = "IDLE_DELAY",
+    glide_step: f64 = "GLIDE_STEP",
+    max_catch_up: f64 = "MAX_CATCH_UP",
+    device_poll_interval: f64 = "DEVICE_POLL_INTERVAL",
+    physical_velocity: bool = "PHYSICAL_VELOCITY",
+    pixels_per_mm: Float = "PIXELS_PER_MM",
+    glide_press: press::PressCurve = "GLIDE_PRESS",
+    full_press_size: Float = "FULL_PRESS_SIZE",
+    full_press_pressure: Float = "FULL_PRESS_PRESSURE",
+    press_window: f64 = "PRESS_WINDOW",
+}
+
+static CONFIG: OnceLock<RwLock<Arc<Config>>> = OnceLock::new();
+
+fn config_cell() -> &'static RwLock<Arc<Config>> {
+    CONFIG.get_or_init(|| RwLock::new(Arc::new(Config::defaults())))
+}
+
+thread_local! {
+    // Set by `with_config` while simulating, so simulations never touch the live configuration
+    static CONFIG_OVERRIDE: RefCell<Option<Arc<Config>>> = const { RefCell::new(None) };
+}
+
+// Cheap snapshot of the live configuration, callers keep it for the duration of one update
+pub fn config() -> Arc<Config> {
+    if let Some(config) = CONFIG_OVERRIDE.with_borrow(Option::clone) {
+        return config;
+    }
+    config_cell().read().expect("config lock poisoned").clone()
+}
+
+// Runs `f` with `config` in place of the live configuration, on this thread only
+pub fn with_config<T>(config: Config, f: impl FnOnce() -> T) -> T {
+    let previous = CONFIG_OVERRIDE.replace(Some(Arc::new(config)));
+    let result = f();
+    CONFIG_OVERRIDE.set(previous);
+    result
+}
+
+pub fn set_config(config: Config) {
+    *config_cell().write().expect("config lock poisoned") = Arc::new(config);
+}
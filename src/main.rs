@@ -1,46 +1,21 @@
-pub mod app;
-pub mod controller;
-pub mod engine;
-pub mod tests;
-pub mod trackpad;
-pub mod utils;
-
 use chrono::Local;
-use cidre::cg::Float;
+use lapsus::sweep;
 use log::LevelFilter;
 use std::env;
 use std::fs::File;
 use std::io::Write;
-use std::sync::OnceLock;
-
-pub struct Config {
-    maximum_momentum_speed: f64,
-    trackpad_velocity_gain: f64,
-    glide_decay_per_second: f64,
-    minimum_glide_velocity: f64,
-    glide_stop_speed_factor: f64,
-    velocity_smoothing: f64,
-    min_dt: f64,
-    multi_finger_suppression_deadline: f64,
-}
 
-static CONFIG: OnceLock<Config> = OnceLock::new();
-
-pub fn config() -> &'static Config {
-    CONFIG.get_or_init(|| Config {
-        maximum_momentum_speed: env!("MAXIMUM_MOMENTUM_SPEED").parse::<Float>().unwrap(),
-        trackpad_velocity_gain: env!("TRACKPAD_VELOCITY_GAIN").parse::<Float>().unwrap(),
-        glide_decay_per_second: env!("GLIDE_DECAY_PER_SECOND").parse::<Float>().unwrap(),
-        minimum_glide_velocity: env!("MINIMUM_GLIDE_VELOCITY").parse::<Float>().unwrap(),
-        glide_stop_speed_factor: env!("GLIDE_STOP_SPEED_FACTOR").parse::<Float>().unwrap(),
-        velocity_smoothing: env!("VELOCITY_SMOOTHING").parse::<Float>().unwrap(),
-        min_dt: env!("MIN_DT").parse::<Float>().unwrap(),
-        multi_finger_suppression_deadline: env!("MULTI_FINGER_SUPPRESSION_DEADLINE")
-            .parse::<f64>()
-            .unwrap(),
-    })
-}
 fn main() {
+    // Offline tools run without the app
+    let args: Vec<String> = env::args().skip(1).collect();
+    if args.first().is_some_and(|command| command == "sweep") {
+        std::process::exit(sweep::main(&args[1..]));
+    }
+    if !cfg!(target_os = "macos") {
+        eprintln!("Lapsus: the app needs macOS, only `Lapsus sweep` runs here");
+        std::process::exit(1);
+    }
+
     let target = Box::new(File::create("lapsus_log.txt").expect("Can't create file"));
 
     env_logger::Builder::new()
@@ -59,5 +34,6 @@ fn main() {
         })
         .init();
 
-    app::run();
+    #[cfg(target_os = "macos")]
+    lapsus::app::run();
 }
@@ -0,0 +1,152 @@
+// Piecewise-linear curves over one input, written in config as `input:output` control points, e.g.
+// `0:0.6,300:1,1500:2`, or as the name of a preset. What the points may be comes from a
+// `CurveShape`, so each setting that takes a curve only names its own
+
+use crate::geometry::Float;
+use std::fmt;
+use std::ops::RangeInclusive;
+
+// How the outputs of neighbouring control points may relate
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Monotonicity {
+    Any,
+    // Input times output never decreases, as for a gain on a speed
+    NondecreasingProduct,
+}
+
+#[derive(Debug)]
+pub struct CurveShape {
+    // What the two sides of a control point are called in messages
+    pub input: &'static str,
+    pub output: &'static str,
+    pub inputs: RangeInclusive<Float>,
+    pub outputs: RangeInclusive<Float>,
+    pub monotonicity: Monotonicity,
+    pub presets: &'static [(&'static str, &'static [(Float, Float)])],
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct PiecewiseLinear {
+    // Strictly increasing in input. Empty when off
+    points: Vec<(Float, Float)>,
+    preset: Option<&'static str>,
+}
+
+impl PiecewiseLinear {
+    pub fn new(shape: &CurveShape, points: Vec<(Float, Float)>) -> Result<Self, String> {
+        if points.is_empty() {
+            return Err("a curve needs at least one control point".to_string());
+        }
+        for &(input, output) in &points {
+            if !shape.inputs.contains(&input) || !shape.outputs.contains(&output) {
+                return Err(format!("invalid control point {}:{}", input, output));
+            }
+        }
+        for pair in points.windows(2) {
+            let ((x0, y0), (x1, y1)) = (pair[0], pair[1]);
+            if x1 <= x0 {
+                return Err(format!(
+                    "{}s must increase, got {} after {}",
+                    shape.input, x1, x0
+                ));
+            }
+            match shape.monotonicity {
+                Monotonicity::Any => {}
+                Monotonicity::NondecreasingProduct => {
+                    // x * y(x) is a parabola on each segment, so it is monotonic there exactly
+                    // when its slope is not negative at both ends
+                    let slope = (y1 - y0) / (x1 - x0);
+                    if y0 + x0 * slope < 0.0 || y1 + x1 * slope < 0.0 {
+                        return Err(format!(
+                            "{} times {} decreases between {} and {}",
+                            shape.input, shape.output, x0, x1
+                        ));
+                    }
+                }
+            }
+        }
+        Ok(Self {
+            points,
+            preset: None,
+        })
+    }
+
+    pub fn off() -> Self {
+        Self {
+            points: Vec::new(),
+            preset: None,
+        }
+    }
+
+    pub fn preset(shape: &CurveShape, name: &str) -> Option<Self> {
+        let (name, points) = shape.presets.iter().find(|(preset, _)| *preset == name)?;
+        Some(Self {
+            points: points.to_vec(),
+            preset: Some(name),
+        })
+    }
+
+    pub fn is_off(&self) -> bool {
+        self.points.is_empty()
+    }
+
+    // Output at `input`, flat beyond the first and last control points and 1 when off
+    pub fn value(&self, input: Float) -> Float {
+        let Some(&(first_input, first_output)) = self.points.first() else {
+            return 1.0;
+        };
+        if input <= first_input {
+            return first_output;
+        }
+        for pair in self.points.windows(2) {
+            let ((x0, y0), (x1, y1)) = (pair[0], pair[1]);
+            if input <= x1 {
+                return y0 + (y1 - y0) * (input - x0) / (x1 - x0);
+            }
+        }
+        self.points[self.points.len() - 1].1
+    }
+
+    pub fn parse(shape: &CurveShape, value: &str) -> Result<Self, String> {
+        let value = value.trim();
+        if value == "off" {
+            return Ok(Self::off());
+        }
+        if let Some(curve) = Self::preset(shape, value) {
+            return Ok(curve);
+        }
+        let mut points = Vec::new();
+        for point in value.split(',') {
+            let parsed = point.split_once(':').and_then(|(input, output)| {
+                Some((input.trim().parse().ok()?, output.trim().parse().ok()?))
+            });
+            match parsed {
+                Some(point) => points.push(point),
+                None => {
+                    return Err(format!(
+                        "expected `{}:{}`, got `{}`",
+                        shape.input, shape.output, point
+                    ));
+                }
+            }
+        }
+        Self::new(shape, points)
+    }
+}
+
+impl fmt::Display for PiecewiseLinear {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if let Some(name) = self.preset {
+            return f.write_str(name);
+        }
+        if self.is_off() {
+            return f.write_str("off");
+        }
+        let points: Vec<String> = self
+            .points
+            .iter()
+            .map(|(input, output)| format!("{}:{}", input, output))
+            .collect();
+        f.write_str(&points.join(","))
+    }
+}
@@ -0,0 +1,116 @@
+// Glide strength from how firmly the fingers rest on the trackpad: a light, quick flick glides
+// further and a heavy press damps or cancels the glide. Written in config as `weight:strength`
+// control points over the contact weight, 0 for the lightest touch and 1 for a full press, e.g.
+// `0:1.3,0.5:1,0.9:0`, or as the name of a preset
+
+use crate::frame::Contact;
+use crate::geometry::Float;
+use crate::piecewise::{CurveShape, Monotonicity, PiecewiseLinear};
+use std::collections::VecDeque;
+use std::fmt;
+use std::str::FromStr;
+
+// Any strength from none up, so a heavy press can glide more or less than a light one
+const SHAPE: CurveShape = CurveShape {
+    input: "weight",
+    output: "strength",
+    inputs: 0.0..=1.0,
+    outputs: 0.0..=Float::MAX,
+    monotonicity: Monotonicity::Any,
+    presets: PRESETS,
+};
+
+const PRESETS: &[(&str, &[(Float, Float)])] = &[
+    ("gentle", &[(0.0, 1.15), (0.5, 1.0), (1.0, 0.5)]),
+    ("flick", &[(0.0, 1.3), (0.4, 1.0), (0.9, 0.0)]),
+];
+
+// Contact weight to glide strength
+#[derive(Debug, Clone, PartialEq)]
+pub struct PressCurve(PiecewiseLinear);
+
+impl PressCurve {
+    pub fn new(points: Vec<(Float, Float)>) -> Result<Self, String> {
+        PiecewiseLinear::new(&SHAPE, points).map(Self)
+    }
+
+    pub fn off() -> Self {
+        Self(PiecewiseLinear::off())
+    }
+
+    pub fn preset(name: &str) -> Option<Self> {
+        PiecewiseLinear::preset(&SHAPE, name).map(Self)
+    }
+
+    pub fn is_off(&self) -> bool {
+        self.0.is_off()
+    }
+
+    // Multiplier on the release velocity at `weight`, flat beyond the first and last points
+    pub fn strength(&self, weight: Float) -> Float {
+        self.0.value(weight)
+    }
+}
+
+impl FromStr for PressCurve {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        PiecewiseLinear::parse(&SHAPE, value).map(Self)
+    }
+}
+
+impl fmt::Display for PressCurve {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+// Weight of the heaviest finger in a frame, from 0 to 1. Size and pressure count relative to the
+// values of a full press, and a full value of 0 leaves that reading out
+pub fn contact_weight(contacts: &[Contact], full_size: Float, full_pressure: Float) -> Float {
+    let relative = |value: Float, full: Float| if full > 0.0 { value / full } else { 0.0 };
+    contacts
+        .iter()
+        .map(|contact| {
+            relative(contact.size, full_size).max(relative(contact.pressure, full_pressure))
+        })
+        .fold(0.0, Float::max)
+        .clamp(0.0, 1.0)
+}
+
+// The heaviest weight over the last moments of a touch. Fingers lighten as they leave the surface,
+// so the weight at lift-off alone would make every release look like a flick
+#[derive(Debug, Default)]
+pub struct PressTracker {
+    history: VecDeque<(f64, Float)>,
+    weight: Float,
+}
+
+impl PressTracker {
+    pub fn update(&mut self, timestamp: f64, weight: Float, window: f64) {
+        self.history.push_back((timestamp, weight));
+        while self
+            .history
+            .front()
+            .is_some_and(|(time, _)| *time < timestamp - window)
+        {
+            self.history.pop_front();
+        }
+        self.weight = self
+            .history
+            .iter()
+            .map(|(_, weight)| *weight)
+            .fold(0.0, Float::max);
+    }
+
+    // Kept after lift-off, until the next touch starts
+    pub fn weight(&self) -> Float {
+        self.weight
+    }
+
+    pub fn reset(&mut self) {
+        self.history.clear();
+        self.weight = 0.0;
+    }
+}
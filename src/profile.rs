@@ -0,0 +1,155 @@
+// Named sets of config overrides. Built-in presets ship with the app, user profiles are
+// `<name>.profile` files of `key = value` lines in the profile directory
+
+use crate::Config;
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+
+pub const DEFAULT_PROFILE: &str = "default";
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Profile {
+    pub name: String,
+    pub settings: Vec<(String, String)>,
+}
+
+impl Profile {
+    pub fn new(name: &str) -> Self {
+        Self {
+            name: name.to_string(),
+            settings: Vec::new(),
+        }
+    }
+
+    pub fn with(mut self, key: &str, value: &str) -> Self {
+        self.settings.push((key.to_string(), value.to_string()));
+        self
+    }
+
+    // Replaces an earlier value for the same key, so runtime changes keep one entry per key
+    pub fn set(&mut self, key: &str, value: &str) {
+        match self
+            .settings
+            .iter_mut()
+            .find(|(existing, _)| existing == key)
+        {
+            Some(setting) => setting.1 = value.to_string(),
+            None => self.settings.push((key.to_string(), value.to_string())),
+        }
+    }
+
+    // The config this profile selects, with values changed at runtime layered on top
+    pub fn layered(&self, overrides: &Profile) -> Result<Config, String> {
+        overrides.apply(&self.apply(&Config::defaults())?)
+    }
+
+    // Applied on top of the build defaults so switching between profiles is not order dependent
+    pub fn apply(&self, base: &Config) -> Result<Config, String> {
+        let mut config = base.clone();
+        for (key, value) in &self.settings {
+            config
+                .set(key, value)
+                .map_err(|error| format!("profile {}: {}", self.name, error))?;
+        }
+        Ok(config)
+    }
+
+    pub fn parse(name: &str, text: &str) -> Result<Self, String> {
+        let mut profile = Self::new(name);
+        for (index, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let (key, value) = line.split_once('=').ok_or_else(|| {
+                format!("profile {} line {}: expected key = value", name, index + 1)
+            })?;
+            let (key, value) = (key.trim(), value.trim());
+            if !Config::KEYS.contains(&key) {
+                return Err(format!(
+                    "profile {} line {}: unknown config key `{}`",
+                    name,
+                    index + 1,
+                    key
+                ));
+            }
+            profile = profile.with(key, value);
+        }
+        Ok(profile)
+    }
+
+    pub fn to_text(&self) -> String {
+        let mut text = String::new();
+        for (key, value) in &self.settings {
+            text.push_str(&format!("{} = {}\n", key, value));
+        }
+        text
+    }
+}
+
+pub fn builtin(name: &str) -> Option<Profile> {
+    match name {
+        DEFAULT_PROFILE => Some(Profile::new(DEFAULT_PROFILE)),
+        "short" => Some(
+            Profile::new("short")
+                .with("glide_decay_per_second", "9.5")
+                .with("minimum_glide_velocity", "320.0"),
+        ),
+        "long" => Some(
+            Profile::new("long")
+                .with("glide_decay_per_second", "4.5")
+                .with("minimum_glide_velocity", "180.0"),
+        ),
+        _ => None,
+    }
+}
+
+// Where user files (profiles, rules) live
+pub fn support_dir() -> PathBuf {
+    if let Some(dir) = std::env::var_os("LAPSUS_SUPPORT_DIR") {
+        return PathBuf::from(dir);
+    }
+    let home = std::env::var_os("HOME")
+        .map(PathBuf::from)
+        .unwrap_or_default();
+    home.join("Library/Application Support/Lapsus")
+}
+
+pub fn profile_dir() -> PathBuf {
+    match std::env::var_os("LAPSUS_PROFILE_DIR") {
+        Some(dir) => PathBuf::from(dir),
+        None => support_dir().join("profiles"),
+    }
+}
+
+// Names become file names in the profile directory, so they may not point anywhere else
+pub fn check_name(name: &str) -> Result<(), String> {
+    if name.is_empty() || name.starts_with('.') || name.contains(['/', '\\']) {
+        return Err(format!("invalid profile name `{}`", name));
+    }
+    Ok(())
+}
+
+// A profile file on disk shadows a built-in preset of the same name
+pub fn load(name: &str) -> Result<Profile, String> {
+    check_name(name)?;
+    let path = profile_dir().join(format!("{}.profile", name));
+    match fs::read_to_string(&path) {
+        Ok(text) => Profile::parse(name, &text),
+        Err(error) if error.kind() == io::ErrorKind::NotFound => {
+            builtin(name).ok_or_else(|| format!("unknown profile `{}`", name))
+        }
+        Err(error) => Err(format!("{}: {}", path.display(), error)),
+    }
+}
+
+pub fn save(profile: &Profile) -> io::Result<PathBuf> {
+    check_name(&profile.name)
+        .map_err(|error| io::Error::new(io::ErrorKind::InvalidInput, error))?;
+    let dir = profile_dir();
+    fs::create_dir_all(&dir)?;
+    let path = dir.join(format!("{}.profile", profile.name));
+    fs::write(&path, profile.to_text())?;
+    Ok(path)
+}
@@ -0,0 +1,288 @@
+// Line based protocol spoken over the control socket. A client sends one command per line and
+// gets exactly one response line back: `ok key=value ...` or `error <message>`
+
+use crate::profile;
+use std::io::{self, BufRead, BufReader, Write};
+use std::os::unix::net::UnixStream;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+// How long the server waits for the main thread, and the client a little longer for the server
+const REPLY_TIMEOUT: Duration = Duration::from_secs(2);
+// Fitting a calibration replays every trial hundreds of times
+const FIT_REPLY_TIMEOUT: Duration = Duration::from_secs(60);
+const CLIENT_GRACE: Duration = Duration::from_secs(3);
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Command {
+    Status,
+    Pause,
+    Resume,
+    Profile(String),
+    Get(String),
+    Set(String, String),
+    RecordStart(PathBuf),
+    RecordStop,
+    Replay(PathBuf),
+    CalibrateStart,
+    CalibrateSave(String),
+    CalibrateCancel,
+}
+
+impl Command {
+    pub fn parse(line: &str) -> Result<Self, String> {
+        let line = line.trim();
+        let (verb, rest) = match line.split_once(char::is_whitespace) {
+            Some((verb, rest)) => (verb, rest.trim()),
+            None => (line, ""),
+        };
+        let command = match (verb, rest) {
+            ("status", "") => Command::Status,
+            ("pause", "") => Command::Pause,
+            ("resume", "") => Command::Resume,
+            ("profile", name) if !name.is_empty() => Command::Profile(name.to_string()),
+            ("get", key) if !key.is_empty() => Command::Get(key.to_string()),
+            ("set", rest) => match rest.split_once(char::is_whitespace) {
+                Some((key, value)) => Command::Set(key.to_string(), value.trim().to_string()),
+                None => return Err("usage: set <key> <value>".to_string()),
+            },
+            ("record", "stop") => Command::RecordStop,
+            ("record", rest) => match rest.split_once(char::is_whitespace) {
+                Some(("start", path)) => Command::RecordStart(PathBuf::from(path.trim())),
+                _ => return Err("usage: record start <path> | record stop".to_string()),
+            },
+            ("replay", path) if !path.is_empty() => Command::Replay(PathBuf::from(path)),
+            ("calibrate", "start") => Command::CalibrateStart,
+            ("calibrate", "cancel") => Command::CalibrateCancel,
+            ("calibrate", rest) => match rest.split_whitespace().collect::<Vec<_>>()[..] {
+                ["save", name] => {
+                    profile::check_name(name)?;
+                    Command::CalibrateSave(name.to_string())
+                }
+                _ => {
+                    return Err(
+                        "usage: calibrate start | calibrate save <profile> | calibrate cancel"
+                            .to_string(),
+                    );
+                }
+            },
+            ("", _) => return Err("empty command".to_string()),
+            _ => return Err(format!("unknown command `{}`", line)),
+        };
+        Ok(command)
+    }
+
+    pub fn reply_timeout(&self) -> Duration {
+        match self {
+            Command::CalibrateSave(_) => FIT_REPLY_TIMEOUT,
+            _ => REPLY_TIMEOUT,
+        }
+    }
+
+    pub fn to_line(&self) -> String {
+        match self {
+            Command::Status => "status".to_string(),
+            Command::Pause => "pause".to_string(),
+            Command::Resume => "resume".to_string(),
+            Command::Profile(name) => format!("profile {}", name),
+            Command::Get(key) => format!("get {}", key),
+            Command::Set(key, value) => format!("set {} {}", key, value),
+            Command::RecordStart(path) => format!("record start {}", path.display()),
+            Command::RecordStop => "record stop".to_string(),
+            Command::Replay(path) => format!("replay {}", path.display()),
+            Command::CalibrateStart => "calibrate start".to_string(),
+            Command::CalibrateSave(name) => format!("calibrate save {}", name),
+            Command::CalibrateCancel => "calibrate cancel".to_string(),
+        }
+    }
+
+    // Command line form, e.g. `["set", "min_dt", "0.004"]`. Paths are made absolute because the
+    // server resolves them against its own working directory
+    pub fn from_args(args: &[String]) -> Result<Self, String> {
+        let args: Vec<&str> = args.iter().map(String::as_str).collect();
+        let command = match args.as_slice() {
+            ["status"] => Command::Status,
+            ["pause"] => Command::Pause,
+            ["resume"] => Command::Resume,
+            ["profile", name] => Command::Profile(name.to_string()),
+            ["get", key] => Command::Get(key.to_string()),
+            ["set", key, value] => Command::Set(key.to_string(), value.to_string()),
+            ["record", "start", path] => Command::RecordStart(absolute(path)?),
+            ["record", "stop"] => Command::RecordStop,
+            ["replay", path] => Command::Replay(absolute(path)?),
+            ["calibrate", "start"] => Command::CalibrateStart,
+            ["calibrate", "save", name] => Command::CalibrateSave(name.to_string()),
+            ["calibrate", "cancel"] => Command::CalibrateCancel,
+            [] => return Err("missing command".to_string()),
+            _ => return Err(format!("invalid command `{}`", args.join(" "))),
+        };
+        Ok(command)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Response {
+    Ok(Vec<(String, String)>),
+    Error(String),
+}
+
+impl Response {
+    pub fn ok() -> Self {
+        Response::Ok(Vec::new())
+    }
+
+    pub fn with(self, key: &str, value: impl ToString) -> Self {
+        match self {
+            Response::Ok(mut fields) => {
+                fields.push((key.to_string(), value.to_string()));
+                Response::Ok(fields)
+            }
+            error => error,
+        }
+    }
+
+    pub fn error(message: impl ToString) -> Self {
+        Response::Error(message.to_string())
+    }
+
+    pub fn field(&self, key: &str) -> Option<&str> {
+        match self {
+            Response::Ok(fields) => fields
+                .iter()
+                .find(|(name, _)| name == key)
+                .map(|(_, value)| value.as_str()),
+            Response::Error(_) => None,
+        }
+    }
+
+    pub fn to_line(&self) -> String {
+        match self {
+            Response::Ok(fields) => {
+                let mut line = "ok".to_string();
+                for (key, value) in fields {
+                    line.push_str(&format!(" {}={}", key, escape(value)));
+                }
+                line
+            }
+            Response::Error(message) => format!("error {}", message.replace('\n', " ")),
+        }
+    }
+
+    pub fn to_json(&self) -> String {
+        match self {
+            Response::Ok(fields) => {
+                let mut json = "{\"ok\":true".to_string();
+                for (key, value) in fields {
+                    json.push_str(&format!(",{}:{}", json_string(key), json_value(value)));
+                }
+                json.push('}');
+                json
+            }
+            Response::Error(message) => {
+                format!("{{\"ok\":false,\"error\":{}}}", json_string(message))
+            }
+        }
+    }
+
+    pub fn parse(line: &str) -> Result<Self, String> {
+        let line = line.trim_end();
+        let (status, rest) = line.split_once(' ').unwrap_or((line, ""));
+        match status {
+            "error" => return Ok(Response::Error(rest.trim().to_string())),
+            "ok" => {}
+            _ => return Err(format!("malformed response `{}`", line)),
+        }
+        let mut fields = Vec::new();
+        for field in rest.split_whitespace() {
+            let (key, value) = field
+                .split_once('=')
+                .ok_or_else(|| format!("malformed field `{}`", field))?;
+            fields.push((key.to_string(), unescape(value)));
+        }
+        Ok(Response::Ok(fields))
+    }
+}
+
+// Values are percent escaped so they can never break the `key=value` framing
+fn escape(value: &str) -> String {
+    value
+        .replace('%', "%25")
+        .replace(' ', "%20")
+        .replace('=', "%3D")
+        .replace('\n', "%0A")
+}
+
+fn unescape(value: &str) -> String {
+    value
+        .replace("%0A", "\n")
+        .replace("%3D", "=")
+        .replace("%20", " ")
+        .replace("%25", "%")
+}
+
+pub fn json_string(value: &str) -> String {
+    let mut json = String::with_capacity(value.len() + 2);
+    json.push('"');
+    for character in value.chars() {
+        match character {
+            '"' => json.push_str("\\\""),
+            '\\' => json.push_str("\\\\"),
+            '\n' => json.push_str("\\n"),
+            character if character.is_control() => {
+                json.push_str(&format!("\\u{:04x}", character as u32))
+            }
+            character => json.push(character),
+        }
+    }
+    json.push('"');
+    json
+}
+
+// Booleans and plain numbers keep their type, everything else is a string
+fn json_value(value: &str) -> String {
+    if value == "true" || value == "false" || is_json_number(value) {
+        value.to_string()
+    } else {
+        json_string(value)
+    }
+}
+
+fn is_json_number(value: &str) -> bool {
+    let unsigned = value.strip_prefix('-').unwrap_or(value);
+    let (whole, fraction) = match unsigned.split_once('.') {
+        Some((whole, fraction)) => (whole, Some(fraction)),
+        None => (unsigned, None),
+    };
+    let all_digits =
+        |part: &str| !part.is_empty() && part.bytes().all(|byte| byte.is_ascii_digit());
+    all_digits(whole)
+        && (whole == "0" || !whole.starts_with('0'))
+        && fraction.is_none_or(all_digits)
+}
+
+fn absolute(path: &str) -> Result<PathBuf, String> {
+    std::path::absolute(path).map_err(|error| format!("{}: {}", path, error))
+}
+
+// Sends one command and waits for its response
+pub fn request(path: &Path, command: &Command) -> io::Result<Response> {
+    let mut stream = UnixStream::connect(path)?;
+    stream.set_read_timeout(Some(command.reply_timeout() + CLIENT_GRACE))?;
+    writeln!(stream, "{}", command.to_line())?;
+    let mut line = String::new();
+    BufReader::new(stream).read_line(&mut line)?;
+    if line.is_empty() {
+        return Err(io::Error::new(
+            io::ErrorKind::UnexpectedEof,
+            "connection closed without a response",
+        ));
+    }
+    Response::parse(&line).map_err(|error| io::Error::new(io::ErrorKind::InvalidData, error))
+}
+
+pub fn socket_path() -> PathBuf {
+    match std::env::var_os("LAPSUS_SOCKET") {
+        Some(path) => PathBuf::from(path),
+        None => std::env::temp_dir().join("lapsus.sock"),
+    }
+}
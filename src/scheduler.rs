@@ -0,0 +1,139 @@
+// Tick scheduling: full rate while a finger is down or a glide runs, and no ticks at all once
+// idle. An idle scheduler is woken from the input callback or the control socket through a `Waker`
+
+use crate::Config;
+use std::fmt;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+pub trait TickTimer {
+    // Repeats every `interval` seconds, replacing any previous schedule
+    fn start(&mut self, interval: f64);
+    fn stop(&mut self);
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TickMode {
+    Active,
+    Idle,
+}
+
+// Callable from any thread at any rate: only the first wake after going idle gets through
+pub struct Waker {
+    armed: AtomicBool,
+    notify: Box<dyn Fn() + Send + Sync>,
+}
+
+impl Waker {
+    pub fn new(notify: impl Fn() + Send + Sync + 'static) -> Arc<Self> {
+        Arc::new(Self {
+            armed: AtomicBool::new(false),
+            notify: Box::new(notify),
+        })
+    }
+
+    pub fn wake(&self) {
+        if self.armed.swap(false, Ordering::AcqRel) {
+            (self.notify)();
+        }
+    }
+}
+
+impl fmt::Debug for Waker {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Waker")
+            .field("armed", &self.armed.load(Ordering::Acquire))
+            .finish()
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TickPolicy {
+    pub active_interval: f64,
+    // Zero means no ticks at all while idle
+    pub idle_interval: f64,
+    // How long to keep the active rate after the last touch or glide
+    pub idle_delay: f64,
+}
+
+impl TickPolicy {
+    pub fn from_config(config: &Config) -> Self {
+        Self {
+            active_interval: config.active_tick_interval,
+            idle_interval: config.idle_tick_interval,
+            idle_delay: config.idle_delay,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct Scheduler {
+    mode: TickMode,
+    interval: Option<f64>,
+    last_busy: f64,
+    waker: Arc<Waker>,
+}
+
+impl Scheduler {
+    pub fn new(waker: Arc<Waker>) -> Self {
+        Self {
+            mode: TickMode::Idle,
+            interval: None,
+            last_busy: 0.0,
+            waker,
+        }
+    }
+
+    pub fn mode(&self) -> TickMode {
+        self.mode
+    }
+
+    // After every tick, `busy` when a finger is down or the engine is not idle
+    pub fn after_tick(
+        &mut self,
+        timer: &mut dyn TickTimer,
+        policy: &TickPolicy,
+        busy: bool,
+        now: f64,
+    ) {
+        if busy {
+            self.last_busy = now;
+        }
+        let mode = if busy || now - self.last_busy < policy.idle_delay {
+            TickMode::Active
+        } else {
+            TickMode::Idle
+        };
+        self.apply(timer, policy, mode);
+    }
+
+    // On the main thread once the waker fired, or at launch. Returns true when it ended an idle period
+    pub fn wake(&mut self, timer: &mut dyn TickTimer, policy: &TickPolicy, now: f64) -> bool {
+        self.last_busy = now;
+        let was_idle = self.mode == TickMode::Idle;
+        self.apply(timer, policy, TickMode::Active);
+        was_idle
+    }
+
+    fn apply(&mut self, timer: &mut dyn TickTimer, policy: &TickPolicy, mode: TickMode) {
+        if mode != self.mode {
+            log::debug!("tick mode {:?}", mode);
+            self.mode = mode;
+        }
+        let interval = match mode {
+            TickMode::Active => Some(policy.active_interval),
+            TickMode::Idle => (policy.idle_interval > 0.0).then_some(policy.idle_interval),
+        };
+        // Armed only while idle, so the input callback costs one atomic swap per frame
+        self.waker
+            .armed
+            .store(mode == TickMode::Idle, Ordering::Release);
+        if interval != self.interval {
+            match interval {
+                Some(interval) => timer.start(interval),
+                None => timer.stop(),
+            }
+            self.interval = interval;
+        }
+    }
+}
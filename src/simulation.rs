@@ -0,0 +1,186 @@
+// Headless replay of contact frames through the trackpad pipeline and the engine, on frame time and
+// without a device or a display. The cursor only moves by gliding: while touching it stays where
+// the touch began, and glides start from the trackpad velocity alone since there is no pointer to
+// compare with. Physical velocity needs the size of the recording trackpad to be given. Calibration
+// and offline sweeps both run on this
+
+use crate::clock::TimeBase;
+use crate::cursor::NullCursorSink;
+use crate::device::NoDevices;
+use crate::engine::Engine;
+use crate::events::EngineEvent;
+use crate::frame::ContactFrame;
+use crate::geometry::{Float, Point, Rect};
+use crate::glide::GlideState;
+use crate::trackpad::{REPLAY_DEVICE, TrackpadMonitor};
+use crate::{Config, with_config};
+
+// A glide still running this long after the last frame is cut off
+const MAX_GLIDE_TIME: f64 = 10.0;
+
+// Settings that need a pointer or a display, and the values the simulation runs with instead
+pub const FIXED_SETTINGS: &[(&str, &str)] = &[
+    ("velocity_blend", "trackpad"),
+    ("acceleration_curve", "off"),
+    ("magnetic_end", "false"),
+    ("glide_ease_time", "0"),
+];
+
+// Settings only the live app reads, for suspending, buttons, ticking, devices and the parts of the
+// fixed settings above that the replay never reaches
+pub const UNUSED_SETTINGS: &[&str] = &[
+    "suspend_while_dragging",
+    "suspend_with_external_mouse",
+    "external_mouse_hold",
+    "suspend_modifier",
+    "drag_glide_mode",
+    "app_rule_interval",
+    "magnetic_speed",
+    "magnetic_radius",
+    "magnetic_strength",
+    "velocity_blend_weight",
+    "active_tick_interval",
+    "idle_tick_interval",
+    "idle_delay",
+    "device_poll_interval",
+];
+
+// The fixed settings `config` has other values for, as `key = fixed instead of value`
+pub fn overridden(config: &Config) -> Vec<String> {
+    FIXED_SETTINGS
+        .iter()
+        .filter_map(|(key, value)| {
+            let current = config.get(key).expect("fixed settings are config keys");
+            (current != *value).then(|| format!("{} = {} instead of {}", key, value, current))
+        })
+        .collect()
+}
+
+// Fails when `config` relies on a setting the simulation would replace, so its results would not
+// match the live behavior
+pub fn check_supported(config: &Config) -> Result<(), String> {
+    match overridden(config).first() {
+        Some(setting) => Err(format!("simulations run with {}", setting)),
+        None => Ok(()),
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Release {
+    pub time: f64,
+    // More than one finger was down at some point of the touch
+    pub multi_finger: bool,
+    pub glided: bool,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct SimulationRun {
+    // Engine events with the frame time they happened at
+    pub events: Vec<(f64, EngineEvent)>,
+    pub releases: Vec<Release>,
+    // Where the cursor came to rest
+    pub position: Point,
+}
+
+// `device_size_mm` is the surface of the trackpad the frames were recorded on, if known
+pub fn run(
+    frames: &[ContactFrame],
+    config: &Config,
+    bounds: Rect,
+    start: Point,
+    device_size_mm: Option<(Float, Float)>,
+) -> SimulationRun {
+    let mut config = config.clone();
+    for (key, value) in FIXED_SETTINGS {
+        config
+            .set(key, value)
+            .expect("fixed settings are valid config values");
+    }
+    let (min_dt, max_catch_up, suppression) = (
+        config.min_dt,
+        config.max_catch_up,
+        config.multi_finger_suppression_deadline,
+    );
+    let glide_step = config.glide_step.max(0.001);
+    with_config(config, || {
+        let mut monitor = TrackpadMonitor::with_enumerator(Box::new(NoDevices));
+        monitor.set_device_size(REPLAY_DEVICE, device_size_mm);
+        let mut engine = Engine::with_cursor_sink(Box::new(NullCursorSink));
+        let receiver = engine.event_channel();
+        engine.update_desktop_bounds(bounds);
+        engine.update_engine_state(start);
+        let mut run = SimulationRun {
+            events: Vec::new(),
+            releases: Vec::new(),
+            position: start,
+        };
+        let mut time = TimeBase::new();
+        let mut snapshots = Vec::new();
+        let mut glide_time = 0.0;
+        let mut suppress_until = f64::NEG_INFINITY;
+        let mut multi_finger = false;
+        for frame in frames {
+            while engine.is_gliding() && glide_time + glide_step <= frame.timestamp {
+                engine.step_glide(1, glide_step);
+                glide_time += glide_step;
+                run.events
+                    .extend(receiver.try_iter().map(|event| (glide_time, event)));
+            }
+            monitor.inject_frame(REPLAY_DEVICE, frame);
+            monitor.drain_frames(&mut snapshots);
+            let Some(touch) = snapshots.last() else {
+                continue;
+            };
+            // Suppression runs on frame time here, the live path uses the wall clock
+            if frame.contacts.len() > 1 {
+                suppress_until = frame.timestamp + suppression;
+                multi_finger = true;
+            }
+            time.observe(touch.frame_timestamp(), 0.0);
+            if touch.is_touching {
+                if let Some(delta_time) = time.touch_delta(min_dt, max_catch_up) {
+                    engine.set_angular_velocity(touch.angular_velocity);
+                    engine.set_physical_velocity(touch.physical_velocity);
+                    engine.handle_touch(
+                        engine.position(),
+                        delta_time,
+                        Some(touch.normalized_velocity),
+                    );
+                }
+            } else if engine.glide_state() == GlideState::Touching {
+                engine.set_contact_weight(touch.contact_weight);
+                let suppress = frame.timestamp < suppress_until;
+                engine.handle_no_touch(engine.position(), 0.0, suppress);
+                run.releases.push(Release {
+                    time: frame.timestamp,
+                    multi_finger,
+                    glided: engine.is_gliding(),
+                });
+                multi_finger = false;
+                glide_time = frame.timestamp;
+                time.reset();
+            }
+            run.events
+                .extend(receiver.try_iter().map(|event| (frame.timestamp, event)));
+        }
+        if engine.glide_state() == GlideState::Touching {
+            let end = frames.last().map_or(0.0, |frame| frame.timestamp);
+            engine.handle_no_touch(engine.position(), 0.0, false);
+            run.releases.push(Release {
+                time: end,
+                multi_finger,
+                glided: engine.is_gliding(),
+            });
+            glide_time = end;
+        }
+        let cutoff = glide_time + MAX_GLIDE_TIME;
+        while engine.is_gliding() && glide_time < cutoff {
+            engine.step_glide(1, glide_step);
+            glide_time += glide_step;
+            run.events
+                .extend(receiver.try_iter().map(|event| (glide_time, event)));
+        }
+        run.position = engine.position();
+        run
+    })
+}
@@ -0,0 +1,285 @@
+// Magnetic glide end: once a glide slows down, pull it onto the nearest UI target instead of
+// letting it stop just short of (or just past) a button
+
+use crate::geometry::{Float, Point, Rect, Vector};
+
+// Supplies candidate target rectangles in desktop coordinates (origin bottom-left, y up)
+pub trait TargetProvider {
+    fn targets_near(&mut self, position: Point, radius: Float) -> Vec<Rect>;
+}
+
+// Fixed set of rectangles, for tests and replays
+pub struct StaticTargets(pub Vec<Rect>);
+
+impl TargetProvider for StaticTargets {
+    fn targets_near(&mut self, position: Point, radius: Float) -> Vec<Rect> {
+        self.0
+            .iter()
+            .filter(|rect| distance(position, nearest_point_on_rect(rect, position)) <= radius)
+            .copied()
+            .collect()
+    }
+}
+
+pub fn distance(a: Point, b: Point) -> Float {
+    ((a.x - b.x) * (a.x - b.x) + (a.y - b.y) * (a.y - b.y)).sqrt()
+}
+
+pub fn nearest_point_on_rect(rect: &Rect, point: Point) -> Point {
+    Point {
+        x: point
+            .x
+            .clamp(rect.origin.x, rect.origin.x + rect.size.width),
+        y: point
+            .y
+            .clamp(rect.origin.y, rect.origin.y + rect.size.height),
+    }
+}
+
+// Where to settle for the closest target within `radius`: just inside its nearest edge, so the
+// cursor ends up over the target rather than on its border. None when already inside one
+pub fn snap_point(position: Point, targets: &[Rect], radius: Float) -> Option<Point> {
+    let mut best: Option<(Float, &Rect)> = None;
+    for rect in targets {
+        let gap = distance(position, nearest_point_on_rect(rect, position));
+        if gap == 0.0 {
+            return None;
+        }
+        if gap <= radius && best.is_none_or(|(best_gap, _)| gap < best_gap) {
+            best = Some((gap, rect));
+        }
+    }
+    let (_, rect) = best?;
+    let inset_x = (rect.size.width / 2.0).min(2.0);
+    let inset_y = (rect.size.height / 2.0).min(2.0);
+    Some(Point {
+        x: position.x.clamp(
+            rect.origin.x + inset_x,
+            rect.origin.x + rect.size.width - inset_x,
+        ),
+        y: position.y.clamp(
+            rect.origin.y + inset_y,
+            rect.origin.y + rect.size.height - inset_y,
+        ),
+    })
+}
+
+// Steers the velocity toward `target` with a critically damped spring of rate `strength` (1/s),
+// so the cursor arrives without overshooting
+pub fn attract(
+    position: Point,
+    velocity: Vector,
+    target: Point,
+    strength: Float,
+    delta_time: Float,
+) -> Vector {
+    let desired = Vector {
+        dx: (target.x - position.x) * strength / 4.0,
+        dy: (target.y - position.y) * strength / 4.0,
+    };
+    let blend = (strength * delta_time).min(1.0);
+    Vector {
+        dx: velocity.dx + (desired.dx - velocity.dx) * blend,
+        dy: velocity.dy + (desired.dy - velocity.dy) * blend,
+    }
+}
+
+#[cfg(target_os = "macos")]
+pub fn default_provider() -> Option<Box<dyn TargetProvider>> {
+    Some(Box::new(accessibility::AccessibilityTargets::new()))
+}
+
+#[cfg(not(target_os = "macos"))]
+pub fn default_provider() -> Option<Box<dyn TargetProvider>> {
+    None
+}
+
+#[cfg(target_os = "macos")]
+mod accessibility {
+    use super::TargetProvider;
+    use crate::geometry::{Float, Point, Rect, Size};
+    use objc2_app_kit::NSScreen;
+    use std::ffi::{CStr, c_void};
+    use std::ptr;
+    use std::time::{Duration, Instant};
+
+    type CFTypeRef = *const c_void;
+    type AXUIElementRef = *const c_void;
+
+    const AX_SUCCESS: i32 = 0;
+    const AX_VALUE_CG_POINT_TYPE: u32 = 1;
+    const AX_VALUE_CG_SIZE_TYPE: u32 = 2;
+    const CF_STRING_ENCODING_UTF8: u32 = 0x0800_0100;
+    // The queries run on the main thread, so a busy target app may only stall a glide briefly:
+    // each round trip gives up after the messaging timeout and sampling stops after the budget
+    const AX_MESSAGING_TIMEOUT: f32 = 0.025;
+    const SAMPLING_BUDGET: Duration = Duration::from_millis(50);
+
+    #[link(name = "ApplicationServices", kind = "framework")]
+    unsafe extern "C" {
+        fn AXUIElementCreateSystemWide() -> AXUIElementRef;
+        fn AXUIElementCopyElementAtPosition(
+            application: AXUIElementRef,
+            x: f32,
+            y: f32,
+            element: *mut AXUIElementRef,
+        ) -> i32;
+        fn AXUIElementCopyAttributeValue(
+            element: AXUIElementRef,
+            attribute: CFTypeRef,
+            value: *mut CFTypeRef,
+        ) -> i32;
+        fn AXUIElementSetMessagingTimeout(element: AXUIElementRef, timeout: f32) -> i32;
+        fn AXValueGetValue(value: CFTypeRef, value_type: u32, value_ptr: *mut c_void) -> bool;
+    }
+
+    #[link(name = "CoreFoundation", kind = "framework")]
+    unsafe extern "C" {
+        fn CFStringCreateWithCString(
+            allocator: CFTypeRef,
+            string: *const std::ffi::c_char,
+            encoding: u32,
+        ) -> CFTypeRef;
+        fn CFRelease(value: CFTypeRef);
+    }
+
+    #[repr(C)]
+    #[derive(Default)]
+    struct AXPoint {
+        x: Float,
+        y: Float,
+    }
+
+    #[repr(C)]
+    #[derive(Default)]
+    struct AXSize {
+        width: Float,
+        height: Float,
+    }
+
+    // Hit tests the accessibility tree around the glide position. Only used once per glide,
+    // when the magnetic phase begins, because every query is a round trip to the target app
+    pub struct AccessibilityTargets {
+        system: AXUIElementRef,
+        position_attribute: CFTypeRef,
+        size_attribute: CFTypeRef,
+    }
+
+    impl AccessibilityTargets {
+        pub fn new() -> Self {
+            unsafe {
+                let system = AXUIElementCreateSystemWide();
+                // Set on the system-wide element it applies to every element
+                if AXUIElementSetMessagingTimeout(system, AX_MESSAGING_TIMEOUT) != AX_SUCCESS {
+                    log::warn!("accessibility messaging timeout not set");
+                }
+                Self {
+                    system,
+                    position_attribute: cf_string(c"AXPosition"),
+                    size_attribute: cf_string(c"AXSize"),
+                }
+            }
+        }
+
+        // `x` and `y` are in the top-left based global space the accessibility API uses
+        fn element_frame(&self, x: Float, y: Float) -> Option<(AXPoint, AXSize)> {
+            unsafe {
+                let mut element: AXUIElementRef = ptr::null();
+                if AXUIElementCopyElementAtPosition(self.system, x as f32, y as f32, &mut element)
+                    != AX_SUCCESS
+                    || element.is_null()
+                {
+                    return None;
+                }
+                let origin =
+                    copy_value::<AXPoint>(element, self.position_attribute, AX_VALUE_CG_POINT_TYPE);
+                let size =
+                    copy_value::<AXSize>(element, self.size_attribute, AX_VALUE_CG_SIZE_TYPE);
+                CFRelease(element);
+                Some((origin?, size?))
+            }
+        }
+    }
+
+    impl TargetProvider for AccessibilityTargets {
+        fn targets_near(&mut self, position: Point, radius: Float) -> Vec<Rect> {
+            let mtm =
+                objc2_foundation::MainThreadMarker::new().expect("must be on the main thread");
+            let Some(screen) = NSScreen::mainScreen(mtm) else {
+                return Vec::new();
+            };
+            let height = screen.frame().size.height;
+            let samples = [
+                (0.0, 0.0),
+                (radius, 0.0),
+                (-radius, 0.0),
+                (0.0, radius),
+                (0.0, -radius),
+            ];
+            let started = Instant::now();
+            let mut targets: Vec<Rect> = Vec::new();
+            for (dx, dy) in samples {
+                if started.elapsed() > SAMPLING_BUDGET {
+                    log::debug!("accessibility too slow, {} targets found", targets.len());
+                    break;
+                }
+                let Some((origin, size)) =
+                    self.element_frame(position.x + dx, height - (position.y + dy))
+                else {
+                    continue;
+                };
+                // Windows and big containers are not targets
+                if size.width > radius * 8.0 || size.height > radius * 8.0 {
+                    continue;
+                }
+                let rect = Rect {
+                    origin: Point {
+                        x: origin.x,
+                        y: height - (origin.y + size.height),
+                    },
+                    size: Size {
+                        width: size.width,
+                        height: size.height,
+                    },
+                };
+                if !targets.contains(&rect) {
+                    targets.push(rect);
+                }
+            }
+            targets
+        }
+    }
+
+    impl Drop for AccessibilityTargets {
+        fn drop(&mut self) {
+            unsafe {
+                CFRelease(self.size_attribute);
+                CFRelease(self.position_attribute);
+                CFRelease(self.system);
+            }
+        }
+    }
+
+    unsafe fn cf_string(value: &CStr) -> CFTypeRef {
+        unsafe { CFStringCreateWithCString(ptr::null(), value.as_ptr(), CF_STRING_ENCODING_UTF8) }
+    }
+
+    unsafe fn copy_value<T: Default>(
+        element: AXUIElementRef,
+        attribute: CFTypeRef,
+        value_type: u32,
+    ) -> Option<T> {
+        unsafe {
+            let mut value: CFTypeRef = ptr::null();
+            if AXUIElementCopyAttributeValue(element, attribute, &mut value) != AX_SUCCESS
+                || value.is_null()
+            {
+                return None;
+            }
+            let mut result = T::default();
+            let ok = AXValueGetValue(value, value_type, &mut result as *mut T as *mut c_void);
+            CFRelease(value);
+            ok.then_some(result)
+        }
+    }
+}
@@ -0,0 +1,65 @@
+// Triple buffer: hands the newest value from one producer thread to one consumer thread without
+// locks. Each side owns a slot and the third is exchanged through one atomic, so the consumer
+// always reads a whole value and a slow consumer only ever skips values
+
+use std::cell::UnsafeCell;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU8, Ordering};
+
+const INDEX: u8 = 0b011;
+// Set when the exchanged slot holds a value the reader has not taken yet
+const FRESH: u8 = 0b100;
+
+struct Shared<T> {
+    slots: [UnsafeCell<T>; 3],
+    middle: AtomicU8,
+}
+
+// Writer and reader never touch the same slot: ownership only moves through `middle`
+unsafe impl<T: Send> Sync for Shared<T> {}
+
+pub struct SnapshotWriter<T> {
+    shared: Arc<Shared<T>>,
+    back: u8,
+}
+
+pub struct SnapshotReader<T> {
+    shared: Arc<Shared<T>>,
+    front: u8,
+}
+
+pub fn snapshot_channel<T: Clone>(initial: T) -> (SnapshotWriter<T>, SnapshotReader<T>) {
+    let shared = Arc::new(Shared {
+        slots: [
+            UnsafeCell::new(initial.clone()),
+            UnsafeCell::new(initial.clone()),
+            UnsafeCell::new(initial),
+        ],
+        middle: AtomicU8::new(1),
+    });
+    let writer = SnapshotWriter {
+        shared: shared.clone(),
+        back: 0,
+    };
+    let reader = SnapshotReader { shared, front: 2 };
+    (writer, reader)
+}
+
+impl<T> SnapshotWriter<T> {
+    pub fn publish(&mut self, value: T) {
+        unsafe { *self.shared.slots[self.back as usize].get() = value };
+        let previous = self.shared.middle.swap(self.back | FRESH, Ordering::AcqRel);
+        self.back = previous & INDEX;
+    }
+}
+
+impl<T: Clone> SnapshotReader<T> {
+    // The newest published value, or the previous one again when nothing new came in
+    pub fn read(&mut self) -> T {
+        if self.shared.middle.load(Ordering::Relaxed) & FRESH != 0 {
+            let previous = self.shared.middle.swap(self.front, Ordering::AcqRel);
+            self.front = previous & INDEX;
+        }
+        unsafe { (*self.shared.slots[self.front as usize].get()).clone() }
+    }
+}
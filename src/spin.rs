@@ -0,0 +1,51 @@
+// Spin of a flick: how fast the direction of the finger was turning just before release. A glide
+// keeps turning at that rate, decaying, which gives arc shaped glides like a curving trackball
+
+use crate::geometry::{Float, Point, Vector};
+use std::f64::consts::{PI, TAU};
+
+// Segments shorter than this (in normalized trackpad units) carry no usable direction
+const MIN_SEGMENT: Float = 0.002;
+
+// Angular velocity in radians per second, counter-clockwise positive, from timestamped centroids
+// ordered oldest first. Zero when the path is too short to tell
+pub fn estimate_angular_velocity(samples: &[(f64, Point)]) -> Float {
+    let mut previous: Option<Float> = None;
+    let mut first_time = None;
+    let mut last_time = 0.0;
+    let mut turned = 0.0;
+    for pair in samples.windows(2) {
+        let ((t0, p0), (t1, p1)) = (pair[0], pair[1]);
+        let (dx, dy) = (p1.x - p0.x, p1.y - p0.y);
+        if (dx * dx + dy * dy).sqrt() < MIN_SEGMENT || t1 <= t0 {
+            continue;
+        }
+        let time = (t0 + t1) / 2.0;
+        let heading = dy.atan2(dx);
+        if let Some(previous_heading) = previous {
+            turned += wrap_angle(heading - previous_heading);
+            last_time = time;
+        } else {
+            first_time = Some(time);
+        }
+        previous = Some(heading);
+    }
+    match first_time {
+        Some(first) if last_time > first => turned / (last_time - first),
+        _ => 0.0,
+    }
+}
+
+// Into (-PI, PI]
+fn wrap_angle(angle: Float) -> Float {
+    let wrapped = angle.rem_euclid(TAU);
+    if wrapped > PI { wrapped - TAU } else { wrapped }
+}
+
+pub fn rotate(vector: Vector, angle: Float) -> Vector {
+    let (sin, cos) = angle.sin_cos();
+    Vector {
+        dx: vector.dx * cos - vector.dy * sin,
+        dy: vector.dx * sin + vector.dy * cos,
+    }
+}
@@ -0,0 +1,162 @@
+// Automatic suspend rules, evaluated every tick from a snapshot of the pointer and keyboard state
+
+use crate::Config;
+use crate::geometry::Point;
+use std::fmt;
+use std::str::FromStr;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Modifier {
+    None,
+    Shift,
+    Control,
+    Option,
+    Command,
+}
+
+impl FromStr for Modifier {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value.to_ascii_lowercase().as_str() {
+            "none" => Ok(Modifier::None),
+            "shift" => Ok(Modifier::Shift),
+            "control" | "ctrl" => Ok(Modifier::Control),
+            "option" | "alt" => Ok(Modifier::Option),
+            "command" | "cmd" => Ok(Modifier::Command),
+            _ => Err(format!("unknown modifier `{}`", value)),
+        }
+    }
+}
+
+impl fmt::Display for Modifier {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Modifier::None => "none",
+            Modifier::Shift => "shift",
+            Modifier::Control => "control",
+            Modifier::Option => "option",
+            Modifier::Command => "command",
+        };
+        f.write_str(name)
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Modifiers {
+    pub shift: bool,
+    pub control: bool,
+    pub option: bool,
+    pub command: bool,
+}
+
+impl Modifiers {
+    pub fn is_down(&self, modifier: Modifier) -> bool {
+        match modifier {
+            Modifier::None => false,
+            Modifier::Shift => self.shift,
+            Modifier::Control => self.control,
+            Modifier::Option => self.option,
+            Modifier::Command => self.command,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct InputSnapshot {
+    // Bit mask of held mouse buttons, bit 0 is the primary button
+    pub mouse_buttons: usize,
+    pub modifiers: Modifiers,
+    pub external_mouse_active: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SuspendReason {
+    Dragging,
+    ExternalMouse,
+    Modifier(Modifier),
+    // An app rule turned gliding off for the frontmost application
+    Application,
+}
+
+impl fmt::Display for SuspendReason {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SuspendReason::Dragging => f.write_str("dragging"),
+            SuspendReason::ExternalMouse => f.write_str("external_mouse"),
+            SuspendReason::Modifier(modifier) => write!(f, "modifier_{}", modifier),
+            SuspendReason::Application => f.write_str("application"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SuspendRules {
+    pub while_dragging: bool,
+    pub with_external_mouse: bool,
+    pub modifier: Modifier,
+}
+
+impl SuspendRules {
+    pub fn from_config(config: &Config) -> Self {
+        Self {
+            while_dragging: config.suspend_while_dragging,
+            with_external_mouse: config.suspend_with_external_mouse,
+            modifier: config.suspend_modifier,
+        }
+    }
+
+    // First matching rule wins
+    pub fn evaluate(&self, input: &InputSnapshot) -> Option<SuspendReason> {
+        if self.while_dragging && input.mouse_buttons != 0 {
+            return Some(SuspendReason::Dragging);
+        }
+        if self.with_external_mouse && input.external_mouse_active {
+            return Some(SuspendReason::ExternalMouse);
+        }
+        if input.modifiers.is_down(self.modifier) {
+            return Some(SuspendReason::Modifier(self.modifier));
+        }
+        None
+    }
+}
+
+// The pointer moving while no finger is on the trackpad, and not to where the engine put it,
+// means another pointing device is in use. It stays active for `hold` seconds after the last such move
+#[derive(Debug, Default)]
+pub struct ExternalMouseDetector {
+    last_position: Option<Point>,
+    was_touching: bool,
+    active_until: f64,
+}
+
+impl ExternalMouseDetector {
+    pub fn update(
+        &mut self,
+        position: Point,
+        engine_position: Point,
+        is_touching: bool,
+        now: f64,
+        hold: f64,
+    ) -> bool {
+        let previous = self.last_position.replace(position);
+        let was_touching = std::mem::replace(&mut self.was_touching, is_touching);
+        if is_touching {
+            self.active_until = 0.0;
+            return false;
+        }
+        // The last frames of a touch can still move the cursor after the fingers are reported up
+        if let Some(previous) = previous.filter(|_| !was_touching) {
+            let moved = distance(previous, position) > 0.5;
+            let placed_by_engine = distance(engine_position, position) <= 0.5;
+            if moved && !placed_by_engine {
+                self.active_until = now + hold;
+            }
+        }
+        now < self.active_until
+    }
+}
+
+fn distance(a: Point, b: Point) -> f64 {
+    ((a.x - b.x) * (a.x - b.x) + (a.y - b.y) * (a.y - b.y)).sqrt()
+}
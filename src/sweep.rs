@@ -0,0 +1,495 @@
+// Offline tuning: replays recorded traces under many combinations of config values and reports
+// glide metrics for each, as CSV or JSON. Run as `Lapsus sweep`, it needs no trackpad or display:
+//
+//     Lapsus sweep --vary glide_decay_per_second=4,6.5,9 --vary trackpad_velocity_gain=0.8..1.2 \
+//         --samples 50 session.trace other.trace
+//
+// Values separated by commas form a grid and every combination is run. As soon as one key has a
+// `min..max` range the sweep samples `--samples` random combinations instead. Settings a replay
+// cannot reproduce are rejected when varied or set by the profile. Where the base config merely
+// defaults to something else, see `simulation::FIXED_SETTINGS`, the sweep names the override
+
+use crate::events::{EngineEvent, GlideEndReason};
+use crate::frame::ContactFrame;
+use crate::geometry::{Float, Point, Rect, Size};
+use crate::protocol::json_string;
+use crate::simulation;
+use crate::trace::read_trace;
+use crate::{Config, profile};
+use std::fmt::Write as _;
+use std::fs;
+use std::path::PathBuf;
+
+const USAGE: &str = "usage: Lapsus sweep [options] <trace>...
+
+options:
+    --vary <key>=<a>,<b>,...   try each value of a config key
+    --vary <key>=<min>..<max>  sample a config key uniformly from a range
+    --samples <n>              random combinations when a range is given (default 100)
+    --seed <n>                 seed of the random search (default 1)
+    --profile <name>           start from a profile instead of the defaults
+    --desktop <width>x<height> desktop size in pixels (default 1440x900)
+    --device-size <w>x<h>      trackpad surface in millimeters, needed for physical_velocity
+    --format csv|json          output format (default csv)
+    --output <path>            write to a file instead of stdout
+
+Replays have no pointer and no display, so they always run with velocity_blend=trackpad,
+acceleration_curve=off, magnetic_end=false and glide_ease_time=0, and say so when that replaces a
+default.";
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Axis {
+    Values(Vec<String>),
+    Range(Float, Float),
+}
+
+impl Axis {
+    // `key=a,b,c` or `key=min..max`
+    pub fn parse(argument: &str) -> Result<(String, Axis), String> {
+        let (key, values) = argument
+            .split_once('=')
+            .ok_or_else(|| format!("expected `<key>=<values>`, got `{}`", argument))?;
+        if !Config::KEYS.contains(&key) {
+            return Err(format!("unknown config key `{}`", key));
+        }
+        if let Some((_, fixed)) = simulation::FIXED_SETTINGS
+            .iter()
+            .find(|(fixed, _)| *fixed == key)
+        {
+            return Err(format!(
+                "{} cannot be varied, replays run with {} = {}",
+                key, key, fixed
+            ));
+        }
+        if simulation::UNUSED_SETTINGS.contains(&key) {
+            return Err(format!("{} has no effect on a replay", key));
+        }
+        let axis = match values.split_once("..") {
+            Some((min, max)) => match (min.parse::<Float>(), max.parse::<Float>()) {
+                (Ok(min), Ok(max)) if min <= max => Axis::Range(min, max),
+                _ => return Err(format!("invalid range `{}` for {}", values, key)),
+            },
+            None => Axis::Values(values.split(',').map(str::to_string).collect()),
+        };
+        Ok((key.to_string(), axis))
+    }
+}
+
+// xorshift64*, enough to spread samples and reproducible from a seed
+struct Rng(u64);
+
+impl Rng {
+    fn new(seed: u64) -> Self {
+        Self(seed.max(1))
+    }
+
+    fn next_unit(&mut self) -> Float {
+        self.0 ^= self.0 >> 12;
+        self.0 ^= self.0 << 25;
+        self.0 ^= self.0 >> 27;
+        (self.0.wrapping_mul(0x2545_f491_4f6c_dd1d) >> 11) as Float / (1u64 << 53) as Float
+    }
+}
+
+// Every grid combination, or `samples` random ones when an axis is a range
+pub fn combinations(
+    axes: &[(String, Axis)],
+    samples: usize,
+    seed: u64,
+) -> Vec<Vec<(String, String)>> {
+    let random = axes.iter().any(|(_, axis)| matches!(axis, Axis::Range(..)));
+    if random {
+        let mut rng = Rng::new(seed);
+        return (0..samples)
+            .map(|_| {
+                axes.iter()
+                    .map(|(key, axis)| {
+                        let value = match axis {
+                            Axis::Range(min, max) => {
+                                format!("{:.4}", min + (max - min) * rng.next_unit())
+                            }
+                            Axis::Values(values) => {
+                                let index = (rng.next_unit() * values.len() as Float) as usize;
+                                values[index.min(values.len() - 1)].clone()
+                            }
+                        };
+                        (key.clone(), value)
+                    })
+                    .collect()
+            })
+            .collect();
+    }
+    let mut combinations = vec![Vec::new()];
+    for (key, axis) in axes {
+        let Axis::Values(values) = axis else {
+            continue;
+        };
+        combinations = combinations
+            .into_iter()
+            .flat_map(|combination: Vec<(String, String)>| {
+                values.iter().map(move |value| {
+                    let mut combination = combination.clone();
+                    combination.push((key.clone(), value.clone()));
+                    combination
+                })
+            })
+            .collect();
+    }
+    combinations
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Distribution {
+    pub count: usize,
+    pub mean: Float,
+    pub min: Float,
+    pub p50: Float,
+    pub p90: Float,
+    pub max: Float,
+}
+
+impl Distribution {
+    pub fn of(mut values: Vec<Float>) -> Self {
+        if values.is_empty() {
+            return Self::default();
+        }
+        values.sort_by(Float::total_cmp);
+        // Nearest rank
+        let percentile = |p: Float| {
+            let rank = (p * values.len() as Float).ceil() as usize;
+            values[rank.clamp(1, values.len()) - 1]
+        };
+        Self {
+            count: values.len(),
+            mean: values.iter().sum::<Float>() / values.len() as Float,
+            min: values[0],
+            p50: percentile(0.5),
+            p90: percentile(0.9),
+            max: values[values.len() - 1],
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Metrics {
+    pub releases: usize,
+    pub glides: usize,
+    // Glides started by a touch that had more than one finger down at some point
+    pub spurious_glides: usize,
+    // Path length of each glide, in pixels
+    pub glide_distance: Distribution,
+    // Seconds from lift-off until a glide slowed to a stop, glides caught by a finger left out
+    pub time_to_stop: Distribution,
+}
+
+// `device_size_mm` is the trackpad the sessions were recorded on, physical velocity needs it
+pub fn evaluate(
+    sessions: &[Vec<ContactFrame>],
+    config: &Config,
+    bounds: Rect,
+    device_size_mm: Option<(Float, Float)>,
+) -> Metrics {
+    let start = Point {
+        x: bounds.origin.x + bounds.size.width / 2.0,
+        y: bounds.origin.y + bounds.size.height / 2.0,
+    };
+    let mut metrics = Metrics::default();
+    let mut distances = Vec::new();
+    let mut stop_times = Vec::new();
+    for frames in sessions {
+        let run = simulation::run(frames, config, bounds, start, device_size_mm);
+        metrics.releases += run.releases.len();
+        metrics.spurious_glides += run
+            .releases
+            .iter()
+            .filter(|release| release.multi_finger && release.glided)
+            .count();
+        let mut glide_start = None;
+        for (time, event) in &run.events {
+            match event {
+                EngineEvent::GlideStart { .. } => {
+                    metrics.glides += 1;
+                    glide_start = Some(*time);
+                }
+                EngineEvent::GlideEnd {
+                    reason, distance, ..
+                } => {
+                    distances.push(*distance);
+                    if let Some(started) = glide_start.take()
+                        && *reason != GlideEndReason::Caught
+                    {
+                        stop_times.push(time - started);
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+    metrics.glide_distance = Distribution::of(distances);
+    metrics.time_to_stop = Distribution::of(stop_times);
+    metrics
+}
+
+const COLUMNS: &[&str] = &[
+    "releases",
+    "glides",
+    "spurious_glides",
+    "glide_distance_mean",
+    "glide_distance_p50",
+    "glide_distance_p90",
+    "glide_distance_max",
+    "time_to_stop_mean",
+    "time_to_stop_p50",
+    "time_to_stop_p90",
+];
+
+fn metric_values(metrics: &Metrics) -> [String; 10] {
+    let distance = &metrics.glide_distance;
+    let stop = &metrics.time_to_stop;
+    [
+        metrics.releases.to_string(),
+        metrics.glides.to_string(),
+        metrics.spurious_glides.to_string(),
+        format!("{:.1}", distance.mean),
+        format!("{:.1}", distance.p50),
+        format!("{:.1}", distance.p90),
+        format!("{:.1}", distance.max),
+        format!("{:.3}", stop.mean),
+        format!("{:.3}", stop.p50),
+        format!("{:.3}", stop.p90),
+    ]
+}
+
+pub type Row = (Vec<(String, String)>, Metrics);
+
+// Curves are written with commas, so such values are quoted
+fn csv_field(value: &str) -> String {
+    if value.contains([',', '"']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
+
+pub fn to_csv(rows: &[Row]) -> String {
+    let Some((settings, _)) = rows.first() else {
+        return String::new();
+    };
+    let mut header: Vec<&str> = settings.iter().map(|(key, _)| key.as_str()).collect();
+    header.extend(COLUMNS);
+    let mut csv = header.join(",") + "\n";
+    for (settings, metrics) in rows {
+        let mut fields: Vec<String> = settings.iter().map(|(_, value)| csv_field(value)).collect();
+        fields.extend(metric_values(metrics));
+        let _ = writeln!(csv, "{}", fields.join(","));
+    }
+    csv
+}
+
+pub fn to_json(rows: &[Row]) -> String {
+    let distribution = |distribution: &Distribution, precision: usize| {
+        format!(
+            "{{\"count\":{},\"mean\":{:.p$},\"min\":{:.p$},\"p50\":{:.p$},\"p90\":{:.p$},\"max\":{:.p$}}}",
+            distribution.count,
+            distribution.mean,
+            distribution.min,
+            distribution.p50,
+            distribution.p90,
+            distribution.max,
+            p = precision
+        )
+    };
+    let rows: Vec<String> = rows
+        .iter()
+        .map(|(settings, metrics)| {
+            let settings: Vec<String> = settings
+                .iter()
+                .map(|(key, value)| format!("{}:{}", json_string(key), json_string(value)))
+                .collect();
+            format!(
+                "{{\"settings\":{{{}}},\"releases\":{},\"glides\":{},\"spurious_glides\":{},\"glide_distance\":{},\"time_to_stop\":{}}}",
+                settings.join(","),
+                metrics.releases,
+                metrics.glides,
+                metrics.spurious_glides,
+                distribution(&metrics.glide_distance, 1),
+                distribution(&metrics.time_to_stop, 3)
+            )
+        })
+        .collect();
+    format!("[{}]\n", rows.join(",\n"))
+}
+
+struct Options {
+    axes: Vec<(String, Axis)>,
+    samples: usize,
+    seed: u64,
+    base: Config,
+    bounds: Rect,
+    device_size_mm: Option<(Float, Float)>,
+    json: bool,
+    output: Option<PathBuf>,
+    traces: Vec<PathBuf>,
+}
+
+fn parse_options(args: &[String]) -> Result<Options, String> {
+    let mut options = Options {
+        axes: Vec::new(),
+        samples: 100,
+        seed: 1,
+        base: Config::defaults(),
+        bounds: Rect {
+            origin: Point { x: 0.0, y: 0.0 },
+            size: Size {
+                width: 1440.0,
+                height: 900.0,
+            },
+        },
+        device_size_mm: None,
+        json: false,
+        output: None,
+        traces: Vec::new(),
+    };
+    let mut arguments = args.iter();
+    while let Some(argument) = arguments.next() {
+        let mut value = |name: &str| {
+            arguments
+                .next()
+                .ok_or_else(|| format!("{} needs a value", name))
+        };
+        match argument.as_str() {
+            "--vary" => options.axes.push(Axis::parse(value("--vary")?)?),
+            "--samples" => {
+                options.samples = value("--samples")?
+                    .parse()
+                    .map_err(|_| "--samples needs a number".to_string())?
+            }
+            "--seed" => {
+                options.seed = value("--seed")?
+                    .parse()
+                    .map_err(|_| "--seed needs a number".to_string())?
+            }
+            "--profile" => {
+                let profile = profile::load(value("--profile")?)?;
+                options.base = profile.apply(&Config::defaults())?;
+                for (key, _) in &profile.settings {
+                    let fixed = simulation::FIXED_SETTINGS
+                        .iter()
+                        .find(|(fixed, _)| fixed == key);
+                    if let Some((_, fixed)) = fixed
+                        && options.base.get(key).as_deref() != Some(*fixed)
+                    {
+                        return Err(format!(
+                            "profile {} sets {}, replays run with {} = {}",
+                            profile.name, key, key, fixed
+                        ));
+                    }
+                }
+            }
+            "--desktop" => {
+                let (width, height) = parse_size(value("--desktop")?)?;
+                options.bounds.size = Size { width, height };
+            }
+            "--device-size" => options.device_size_mm = Some(parse_size(value("--device-size")?)?),
+            "--format" => {
+                options.json = match value("--format")?.as_str() {
+                    "csv" => false,
+                    "json" => true,
+                    format => return Err(format!("unknown format `{}`", format)),
+                }
+            }
+            "--output" => options.output = Some(PathBuf::from(value("--output")?)),
+            option if option.starts_with("--") => {
+                return Err(format!("unknown option `{}`", option));
+            }
+            trace => options.traces.push(PathBuf::from(trace)),
+        }
+    }
+    if options.traces.is_empty() {
+        return Err("no traces given".to_string());
+    }
+    // Without a size the replay has no physical velocity and would quietly use the trackpad gain
+    let physical = options.base.physical_velocity
+        || options
+            .axes
+            .iter()
+            .any(|(key, _)| key == "physical_velocity" || key == "pixels_per_mm");
+    if physical && options.device_size_mm.is_none() {
+        return Err("physical_velocity and pixels_per_mm need --device-size".to_string());
+    }
+    Ok(options)
+}
+
+// `<width>x<height>`, both positive
+fn parse_size(size: &str) -> Result<(Float, Float), String> {
+    let parsed = size.split_once('x').and_then(|(width, height)| {
+        Some((width.parse::<Float>().ok()?, height.parse::<Float>().ok()?))
+    });
+    match parsed {
+        Some((width, height)) if width > 0.0 && height > 0.0 => Ok((width, height)),
+        _ => Err(format!("expected `<width>x<height>`, got `{}`", size)),
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Sweep {
+    // Empty when written to `--output`
+    pub report: String,
+    // Base settings the replays ran with another value of, see `simulation::overridden`
+    pub overridden: Vec<String>,
+}
+
+// Runs the sweep and returns the report
+pub fn sweep(args: &[String]) -> Result<Sweep, String> {
+    let options = parse_options(args)?;
+    let overridden = simulation::overridden(&options.base);
+    let sessions = options
+        .traces
+        .iter()
+        .map(|path| read_trace(path).map_err(|error| format!("{}: {}", path.display(), error)))
+        .collect::<Result<Vec<_>, _>>()?;
+    let mut rows = Vec::new();
+    for settings in combinations(&options.axes, options.samples, options.seed) {
+        let mut config = options.base.clone();
+        for (key, value) in &settings {
+            config.set(key, value)?;
+        }
+        let metrics = evaluate(&sessions, &config, options.bounds, options.device_size_mm);
+        rows.push((settings, metrics));
+    }
+    let report = if options.json {
+        to_json(&rows)
+    } else {
+        to_csv(&rows)
+    };
+    let report = match &options.output {
+        Some(path) => {
+            fs::write(path, &report).map_err(|error| format!("{}: {}", path.display(), error))?;
+            String::new()
+        }
+        None => report,
+    };
+    Ok(Sweep { report, overridden })
+}
+
+// Entry point of `Lapsus sweep`, returns the exit code
+pub fn main(args: &[String]) -> i32 {
+    if args.iter().any(|arg| arg == "-h" || arg == "--help") {
+        println!("{}", USAGE);
+        return 0;
+    }
+    match sweep(args) {
+        Ok(sweep) => {
+            // Kept off stdout so the report stays plain CSV or JSON
+            for setting in &sweep.overridden {
+                eprintln!("Lapsus sweep: replayed with {}", setting);
+            }
+            print!("{}", sweep.report);
+            0
+        }
+        Err(error) => {
+            eprintln!("Lapsus sweep: {}\n\n{}", error, USAGE);
+            2
+        }
+    }
+}
@@ -1,10 +1,2308 @@
 #[cfg(test)]
 mod tests {
+    use crate::calibrate::{self, CalibrationSession, Trial};
+    use crate::clock::TimeBase;
+    use crate::control::{ControlServer, Reply};
+    use crate::cursor::{CursorSink, MouseButton, SubpixelAccumulator};
+    use crate::curve::AccelerationCurve;
+    use crate::device::{
+        DeviceChange, DeviceEnumerator, DeviceInfo, DeviceMatcher, DeviceRegistry, DeviceRules,
+        DeviceSettings, DeviceWatch, FrameCallback, NoDevices, TouchDevice,
+    };
+    use crate::engine::{
+        DragGlideMode, Engine, VelocityBlend, VelocitySource, blend_velocity, lock_direction,
+    };
+    use crate::events::{EngineEvent, GlideEndReason};
+    use crate::focus::{AppAction, AppRules, FocusProvider, FocusTracker, FocusedApp};
+    use crate::frame::{Contact, ContactFrame};
+    use crate::geometry::{Point, Rect, Size, Vector};
+    use crate::glide::{GlideEvent, GlideState, IllegalTransition, Transition, transition};
+    use crate::press::{self, PressCurve, PressTracker};
+    use crate::profile::{self, Profile};
+    use crate::protocol::{self, Command, Response};
+    use crate::scheduler::{Scheduler, TickMode, TickPolicy, TickTimer, Waker};
+    use crate::snap::{self, StaticTargets};
+    use crate::snapshot::snapshot_channel;
+    use crate::spin;
+    use crate::suspend::{
+        ExternalMouseDetector, InputSnapshot, Modifier, Modifiers, SuspendReason, SuspendRules,
+    };
+    use crate::sweep::{self, Axis, Distribution};
+    use crate::trace::parse_trace;
+    use crate::trackpad::{FRAME_QUEUE_CAPACITY, TouchSnapshot, TrackpadMonitor};
+    use crate::uinput::{
+        EV_REL, EV_SYN, InputEvent, REL_X, REL_Y, UinputCursorSink, decode_events,
+    };
     use crate::utils::max;
+    use crate::{Config, config, set_config};
+    use std::cell::{Cell, RefCell};
+    use std::collections::HashMap;
+    use std::fs::{self, File};
+    use std::io::{BufRead, BufReader, Write};
+    use std::os::unix::fs::PermissionsExt;
+    use std::os::unix::net::{UnixListener, UnixStream};
+    use std::path::PathBuf;
+    use std::rc::Rc;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::{Arc, Mutex, MutexGuard};
+    use std::thread;
+    use std::time::Duration;
+
+    static CONFIG_LOCK: Mutex<()> = Mutex::new(());
+
+    // The config is global, so tests that depend on it run one at a time on top of the defaults
+    fn test_config(customize: impl FnOnce(&mut Config)) -> TestConfig {
+        let lock = CONFIG_LOCK
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+        let previous = config();
+        let mut config = Config::defaults();
+        customize(&mut config);
+        set_config(config);
+        TestConfig {
+            previous,
+            _lock: lock,
+        }
+    }
+
+    // Puts the config back when the test ends, before the next test may take the lock
+    struct TestConfig {
+        previous: Arc<Config>,
+        _lock: MutexGuard<'static, ()>,
+    }
+
+    impl Drop for TestConfig {
+        fn drop(&mut self) {
+            set_config(Config::clone(&self.previous));
+        }
+    }
+
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    enum Emitted {
+        Move,
+        Drag(MouseButton),
+    }
+
+    // Records every cursor event the engine emits
+    #[derive(Clone, Default)]
+    struct RecordingSink {
+        moves: Rc<RefCell<Vec<(Emitted, Point, Vector)>>>,
+        owned: Rc<Cell<bool>>,
+    }
+
+    impl CursorSink for RecordingSink {
+        fn move_cursor(&mut self, position: Point, delta: Vector) {
+            self.moves
+                .borrow_mut()
+                .push((Emitted::Move, position, delta));
+        }
+
+        fn drag_cursor(&mut self, position: Point, delta: Vector, button: MouseButton) {
+            self.moves
+                .borrow_mut()
+                .push((Emitted::Drag(button), position, delta));
+        }
+
+        fn set_pointer_owned(&mut self, owned: bool) {
+            self.owned.set(owned);
+        }
+    }
+
+    // Touch at `start`, move to `release` in one 5 ms frame and lift
+    fn flick(engine: &mut Engine, start: Point, release: Point) {
+        engine.update_engine_state(start);
+        engine.begin_touch(start);
+        engine.handle_touch(release, 0.005, None);
+        engine.handle_no_touch(release, 0.005, false);
+    }
+
+    // Small deterministic generator so randomized tests are reproducible
+    struct XorShift(u64);
+
+    impl XorShift {
+        fn next_f64(&mut self) -> f64 {
+            self.0 ^= self.0 << 13;
+            self.0 ^= self.0 >> 7;
+            self.0 ^= self.0 << 17;
+            (self.0 >> 11) as f64 / (1u64 << 53) as f64
+        }
+
+        fn range(&mut self, low: f64, high: f64) -> f64 {
+            low + (high - low) * self.next_f64()
+        }
+    }
 
     #[test]
     fn test_min() {
         let result = max(2.0, 3.0);
         assert_eq!(result, 3.0);
     }
+
+    #[test]
+    fn test_uinput_encoding_to_file() {
+        let path = std::env::temp_dir().join(format!("lapsus-uinput-{}.bin", std::process::id()));
+        let mut sink = UinputCursorSink::new(File::create(&path).unwrap());
+        sink.emit_motion(Vector { dx: 3.0, dy: 2.0 }).unwrap();
+        drop(sink);
+
+        let events = decode_events(&fs::read(&path).unwrap());
+        fs::remove_file(&path).unwrap();
+        assert_eq!(
+            events,
+            vec![
+                InputEvent::new(EV_REL, REL_X, 3),
+                InputEvent::new(EV_REL, REL_Y, -2),
+                InputEvent::new(EV_SYN, 0, 0),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_uinput_slow_tail_does_not_stall() {
+        let mut sink = UinputCursorSink::new(Vec::new());
+        let mut accumulator = SubpixelAccumulator::new();
+        for _ in 0..10 {
+            let step = accumulator.step(Vector { dx: 0.3, dy: 0.0 });
+            sink.emit_motion(step).unwrap();
+        }
+        let total: i32 = decode_events(&sink.into_inner())
+            .iter()
+            .filter(|event| event.kind == EV_REL && event.code == REL_X)
+            .map(|event| event.value)
+            .sum();
+        assert_eq!(total, 3);
+    }
+
+    #[test]
+    fn test_subpixel_steps_never_drift() {
+        let mut rng = XorShift(0x9e37_79b9_7f4a_7c15);
+        for _ in 0..200 {
+            let mut accumulator = SubpixelAccumulator::new();
+            let mut ideal = Vector { dx: 0.0, dy: 0.0 };
+            let mut quantized = Vector { dx: 0.0, dy: 0.0 };
+            let scale = rng.range(0.01, 40.0);
+            for _ in 0..500 {
+                let delta = Vector {
+                    dx: rng.range(-scale, scale),
+                    dy: rng.range(-scale, scale),
+                };
+                let step = accumulator.step(delta);
+                assert_eq!(step.dx, step.dx.round());
+                assert_eq!(step.dy, step.dy.round());
+                ideal.dx += delta.dx;
+                ideal.dy += delta.dy;
+                quantized.dx += step.dx;
+                quantized.dy += step.dy;
+                assert!((ideal.dx - quantized.dx).abs() <= 1.0);
+                assert!((ideal.dy - quantized.dy).abs() <= 1.0);
+            }
+        }
+    }
+
+    #[test]
+    fn test_glide_steps_follow_trajectory() {
+        let _config = test_config(|_| {});
+        let mut rng = XorShift(42);
+        for _ in 0..50 {
+            let sink = RecordingSink::default();
+            let mut engine = Engine::with_cursor_sink(Box::new(sink.clone()));
+            let start = Point { x: 500.0, y: 500.0 };
+            let release = Point {
+                x: start.x + rng.range(-40.0, 40.0),
+                y: start.y + rng.range(-40.0, 40.0),
+            };
+            flick(&mut engine, start, release);
+            while engine.is_gliding() {
+                engine.handle_no_touch(release, 0.005, false);
+            }
+
+            let mut quantized = Vector { dx: 0.0, dy: 0.0 };
+            for (_, position, delta) in sink.moves.borrow().iter() {
+                quantized.dx += delta.dx;
+                quantized.dy += delta.dy;
+                assert!((position.x - release.x - quantized.dx).abs() <= 1.0);
+                assert!((position.y - release.y - quantized.dy).abs() <= 1.0);
+            }
+        }
+    }
+
+    #[test]
+    fn test_command_round_trip() {
+        let commands = [
+            Command::Status,
+            Command::Pause,
+            Command::Resume,
+            Command::Profile("long".to_string()),
+            Command::Get("min_dt".to_string()),
+            Command::Set("glide_decay_per_second".to_string(), "5.5".to_string()),
+            Command::RecordStart(PathBuf::from("/tmp/a trace.txt")),
+            Command::RecordStop,
+            Command::CalibrateStart,
+            Command::CalibrateSave("fitted".to_string()),
+            Command::CalibrateCancel,
+        ];
+        for command in commands {
+            assert_eq!(Command::parse(&command.to_line()), Ok(command));
+        }
+        assert!(Command::parse("set min_dt").is_err());
+        assert!(Command::parse("record").is_err());
+        assert!(Command::parse("record starting.txt").is_err());
+        assert!(Command::parse("record start").is_err());
+        assert!(Command::parse("calibrate saved").is_err());
+        assert!(Command::parse("calibrate save").is_err());
+        assert!(Command::parse("calibrate save ../../evil").is_err());
+        assert!(Command::parse("calibrate save .hidden").is_err());
+        assert!(Command::parse("launch").is_err());
+    }
+
+    #[test]
+    fn test_response_round_trip() {
+        let response = Response::ok()
+            .with("path", "/tmp/with space=%.txt")
+            .with("frames", 12);
+        assert_eq!(Response::parse(&response.to_line()), Ok(response));
+        let error = Response::error("unknown config key `x`");
+        assert_eq!(Response::parse(&error.to_line()), Ok(error));
+        assert_eq!(Response::parse("ok"), Ok(Response::ok()));
+        assert!(Response::parse("errorX").is_err());
+        assert!(Response::parse("okay frames=1").is_err());
+    }
+
+    #[test]
+    fn test_config_get_set() {
+        let mut config = Config::defaults();
+        for key in Config::KEYS {
+            assert!(config.get(key).is_some(), "{} has no value", key);
+        }
+        config.set("glide_decay_per_second", "3.25").unwrap();
+        assert_eq!(config.get("glide_decay_per_second").unwrap(), "3.25");
+        assert!(config.set("glide_decay_per_second", "fast").is_err());
+        assert!(config.set("no_such_key", "1").is_err());
+    }
+
+    #[test]
+    fn test_profile_parse_and_apply() {
+        let profile = Profile::parse(
+            "browser",
+            "# slower decay\nglide_decay_per_second = 4.0\n\nminimum_glide_velocity=150\n",
+        )
+        .unwrap();
+        let config = profile.apply(&Config::defaults()).unwrap();
+        assert_eq!(config.get("glide_decay_per_second").unwrap(), "4");
+        assert_eq!(config.get("minimum_glide_velocity").unwrap(), "150");
+        assert_eq!(Profile::parse("browser", &profile.to_text()), Ok(profile));
+        assert!(Profile::parse("broken", "glide_decay_per_second").is_err());
+        assert!(Profile::parse("broken", "speed = 1").is_err());
+        assert!(profile::builtin("long").is_some());
+    }
+
+    #[test]
+    fn test_set_values_survive_profile_switches() {
+        let mut overrides = Profile::new("set");
+        overrides.set("glide_decay_per_second", "3");
+        overrides.set("glide_decay_per_second", "3.25");
+        assert_eq!(overrides.settings.len(), 1);
+
+        // An app rule switching to `long` and back keeps the value set at runtime
+        let long = profile::builtin("long").unwrap();
+        let config = long.layered(&overrides).unwrap();
+        assert_eq!(config.get("glide_decay_per_second").unwrap(), "3.25");
+        assert_eq!(config.get("minimum_glide_velocity").unwrap(), "180");
+        let default = profile::builtin(profile::DEFAULT_PROFILE).unwrap();
+        let config = default.layered(&overrides).unwrap();
+        assert_eq!(config.get("glide_decay_per_second").unwrap(), "3.25");
+        assert_eq!(
+            config.minimum_glide_velocity,
+            Config::defaults().minimum_glide_velocity
+        );
+    }
+
+    #[test]
+    fn test_trace_round_trip() {
+        let frame = ContactFrame::parse_line("12.5 0.25000,0.50000 0.75000,0.10000").unwrap();
+        assert_eq!(frame.contacts.len(), 2);
+        let text = format!("# lapsus trace v1\n{}\n12.51\n", frame.to_line());
+        let frames = parse_trace(text.as_bytes()).unwrap();
+        assert_eq!(frames[0], frame);
+        assert!(frames[1].contacts.is_empty());
+        assert!(parse_trace("12.5 0.1;0.2\n".as_bytes()).is_err());
+
+        // Size and pressure ride along when the device reports them
+        let frame = ContactFrame::parse_line("12.5 0.25000,0.50000,0.8000,120.0000").unwrap();
+        assert_eq!(frame.contacts[0].size, 0.8);
+        assert_eq!(frame.contacts[0].pressure, 120.0);
+        assert_eq!(ContactFrame::parse_line(&frame.to_line()), Ok(frame));
+        assert!(ContactFrame::parse_line("12.5 0.1,0.2,0.3").is_err());
+    }
+
+    #[test]
+    fn test_control_server_round_trip() {
+        let path = std::env::temp_dir().join(format!("lapsus-test-{}.sock", std::process::id()));
+        let server = ControlServer::bind(&path).unwrap();
+        assert!(ControlServer::bind(&path).is_err());
+        // Only the user running Lapsus may connect
+        let mode = fs::metadata(&path).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o600);
+
+        let client_path = path.clone();
+        let client = thread::spawn(move || {
+            let mut stream = UnixStream::connect(&client_path).unwrap();
+            writeln!(stream, "status").unwrap();
+            writeln!(stream, "bogus").unwrap();
+            let mut lines = BufReader::new(stream).lines();
+            let status = lines.next().unwrap().unwrap();
+            let bogus = lines.next().unwrap().unwrap();
+            (status, bogus)
+        });
+
+        // Stand in for the main thread tick
+        let request = loop {
+            if let Some(request) = server.try_recv() {
+                break request;
+            }
+            thread::yield_now();
+        };
+        assert_eq!(request.command, Command::Status);
+        request.respond(Response::ok().with("is_gliding", false));
+
+        let (status, bogus) = client.join().unwrap();
+        assert_eq!(status, "ok is_gliding=false");
+        assert!(bogus.starts_with("error"));
+
+        // Work handed to a worker thread answers once it is done
+        let client_path = path.clone();
+        let client = thread::spawn(move || {
+            protocol::request(&client_path, &Command::CalibrateSave("fitted".to_string()))
+        });
+        let request = loop {
+            if let Some(request) = server.try_recv() {
+                break request;
+            }
+            thread::yield_now();
+        };
+        request.reply(Reply::Later(Box::new(|| {
+            thread::sleep(Duration::from_millis(20));
+            Response::ok().with("profile", "fitted")
+        })));
+        assert_eq!(
+            client.join().unwrap().unwrap(),
+            Response::ok().with("profile", "fitted")
+        );
+        drop(server);
+        assert!(!path.exists());
+
+        // Whatever else sits at the path is left alone
+        fs::write(&path, "not a socket").unwrap();
+        assert!(ControlServer::bind(&path).is_err());
+        assert_eq!(fs::read_to_string(&path).unwrap(), "not a socket");
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_command_from_args() {
+        let args = |line: &str| {
+            line.split_whitespace()
+                .map(String::from)
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            Command::from_args(&args("set min_dt 0.004")),
+            Ok(Command::Set("min_dt".to_string(), "0.004".to_string()))
+        );
+        match Command::from_args(&args("replay trace.txt")) {
+            Ok(Command::Replay(path)) => assert!(path.is_absolute()),
+            other => panic!("unexpected {:?}", other),
+        }
+        assert!(Command::from_args(&args("record")).is_err());
+        assert!(Command::from_args(&[]).is_err());
+    }
+
+    #[test]
+    fn test_response_json() {
+        let response = Response::ok()
+            .with("is_gliding", true)
+            .with("frames", 12)
+            .with("velocity", "1.5,-2.0")
+            .with("profile", "say \"hi\"")
+            .with("odd", "007");
+        assert_eq!(
+            response.to_json(),
+            r#"{"ok":true,"is_gliding":true,"frames":12,"velocity":"1.5,-2.0","profile":"say \"hi\"","odd":"007"}"#
+        );
+        assert_eq!(
+            Response::error("not recording").to_json(),
+            r#"{"ok":false,"error":"not recording"}"#
+        );
+    }
+
+    #[test]
+    fn test_client_against_fake_server() {
+        let path = std::env::temp_dir().join(format!("lapsus-fake-{}.sock", std::process::id()));
+        let _ = fs::remove_file(&path);
+        let listener = UnixListener::bind(&path).unwrap();
+        let server = thread::spawn(move || {
+            let (stream, _) = listener.accept().unwrap();
+            let mut writer = stream.try_clone().unwrap();
+            let mut line = String::new();
+            BufReader::new(stream).read_line(&mut line).unwrap();
+            writeln!(writer, "ok received={}", line.trim().replace(' ', "%20")).unwrap();
+        });
+
+        let response = protocol::request(&path, &Command::Profile("long".to_string())).unwrap();
+        server.join().unwrap();
+        fs::remove_file(&path).unwrap();
+        assert_eq!(response.field("received"), Some("profile long"));
+    }
+
+    #[test]
+    fn test_suspend_rules() {
+        let rules = SuspendRules {
+            while_dragging: true,
+            with_external_mouse: true,
+            modifier: Modifier::Option,
+        };
+        assert_eq!(rules.evaluate(&InputSnapshot::default()), None);
+        let dragging = InputSnapshot {
+            mouse_buttons: 1,
+            external_mouse_active: true,
+            ..Default::default()
+        };
+        assert_eq!(rules.evaluate(&dragging), Some(SuspendReason::Dragging));
+        let holding_option = InputSnapshot {
+            modifiers: Modifiers {
+                option: true,
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        assert_eq!(
+            rules.evaluate(&holding_option),
+            Some(SuspendReason::Modifier(Modifier::Option))
+        );
+        let relaxed = SuspendRules {
+            while_dragging: false,
+            with_external_mouse: false,
+            modifier: Modifier::None,
+        };
+        assert_eq!(relaxed.evaluate(&dragging), None);
+        assert_eq!("cmd".parse::<Modifier>(), Ok(Modifier::Command));
+    }
+
+    #[test]
+    fn test_external_mouse_detection() {
+        let mut detector = ExternalMouseDetector::default();
+        let at = |x: f64| Point { x, y: 100.0 };
+        assert!(!detector.update(at(0.0), at(0.0), false, 0.0, 1.0));
+        // The engine moved the cursor itself
+        assert!(!detector.update(at(10.0), at(10.0), false, 0.1, 1.0));
+        // Something else moved it
+        assert!(detector.update(at(30.0), at(10.0), false, 0.2, 1.0));
+        assert!(detector.update(at(30.0), at(30.0), false, 1.1, 1.0));
+        assert!(!detector.update(at(30.0), at(30.0), false, 1.3, 1.0));
+        // Trailing motion right after the fingers lift does not count
+        assert!(!detector.update(at(30.0), at(30.0), true, 1.4, 1.0));
+        assert!(!detector.update(at(45.0), at(30.0), false, 1.5, 1.0));
+    }
+
+    fn glide_while_dragging(mode: DragGlideMode) -> (bool, Vec<Emitted>) {
+        let _config = test_config(|config| config.drag_glide_mode = mode);
+        let sink = RecordingSink::default();
+        let mut engine = Engine::with_cursor_sink(Box::new(sink.clone()));
+        engine.set_pressed_button(Some(MouseButton::Left));
+        let start = Point { x: 500.0, y: 500.0 };
+        let release = Point { x: 530.0, y: 500.0 };
+        flick(&mut engine, start, release);
+        let glided = engine.is_gliding();
+        for _ in 0..20 {
+            engine.handle_no_touch(release, 0.005, false);
+        }
+        let emitted = sink
+            .moves
+            .borrow()
+            .iter()
+            .map(|(kind, _, _)| *kind)
+            .collect();
+        (glided, emitted)
+    }
+
+    #[test]
+    fn test_drag_glide_modes() {
+        let (glided, emitted) = glide_while_dragging(DragGlideMode::Drag);
+        assert!(glided);
+        assert!(!emitted.is_empty());
+        assert!(
+            emitted
+                .iter()
+                .all(|kind| *kind == Emitted::Drag(MouseButton::Left))
+        );
+
+        let (glided, emitted) = glide_while_dragging(DragGlideMode::Warp);
+        assert!(glided);
+        assert!(emitted.iter().all(|kind| *kind == Emitted::Move));
+
+        let (glided, emitted) = glide_while_dragging(DragGlideMode::Off);
+        assert!(!glided);
+        assert!(emitted.is_empty());
+    }
+
+    #[test]
+    fn test_button_press_stops_glide_when_drag_gliding_is_off() {
+        let _config = test_config(|config| config.drag_glide_mode = DragGlideMode::Off);
+        let mut engine = Engine::with_cursor_sink(Box::new(RecordingSink::default()));
+        let release = Point { x: 530.0, y: 500.0 };
+        flick(&mut engine, Point { x: 500.0, y: 500.0 }, release);
+        assert!(engine.is_gliding());
+        engine.set_pressed_button(MouseButton::from_mask(0b100));
+        engine.handle_no_touch(release, 0.005, false);
+        assert!(!engine.is_gliding());
+    }
+
+    struct FakeFocus {
+        app: RefCell<Option<FocusedApp>>,
+        queries: Cell<usize>,
+    }
+
+    impl FakeFocus {
+        fn new() -> Self {
+            Self {
+                app: RefCell::new(None),
+                queries: Cell::new(0),
+            }
+        }
+
+        fn focus(&self, bundle_id: &str, executable: &str) {
+            *self.app.borrow_mut() = Some(FocusedApp {
+                bundle_id: Some(bundle_id.to_string()),
+                executable: Some(executable.to_string()),
+            });
+        }
+    }
+
+    impl FocusProvider for FakeFocus {
+        fn frontmost(&self) -> Option<FocusedApp> {
+            self.queries.set(self.queries.get() + 1);
+            self.app.borrow().clone()
+        }
+    }
+
+    #[test]
+    fn test_app_rule_priority() {
+        let rules = AppRules::parse(
+            "# games and drawing apps
+             0 bundle:com.adobe.* profile short
+             10 bundle:com.adobe.Photoshop disable
+             5 exe:firefox profile long
+             5 exe:Firefox profile short",
+        )
+        .unwrap();
+        let app = |bundle_id: &str, executable: &str| FocusedApp {
+            bundle_id: Some(bundle_id.to_string()),
+            executable: Some(executable.to_string()),
+        };
+        let action = |app: FocusedApp| rules.evaluate(&app).map(|rule| rule.action.clone());
+        assert_eq!(
+            action(app("com.adobe.Photoshop", "Adobe Photoshop")),
+            Some(AppAction::Disable)
+        );
+        assert_eq!(
+            action(app("com.adobe.Illustrator", "Illustrator")),
+            Some(AppAction::Profile("short".to_string()))
+        );
+        // Same priority keeps the listed order
+        assert_eq!(
+            action(app("org.mozilla.firefox", "Firefox")),
+            Some(AppAction::Profile("long".to_string()))
+        );
+        assert_eq!(action(app("com.apple.Terminal", "Terminal")), None);
+        assert!(AppRules::parse("high bundle:x disable").is_err());
+        assert!(AppRules::parse("1 window:x disable").is_err());
+        assert!(AppRules::parse("1 exe:x profile").is_err());
+    }
+
+    #[test]
+    fn test_focus_tracker_reports_changes() {
+        let rules = AppRules::parse("1 exe:Krita disable\n1 exe:Safari profile long").unwrap();
+        let mut tracker = FocusTracker::new(rules);
+        let focus = FakeFocus::new();
+
+        focus.focus("org.kde.krita", "Krita");
+        assert!(tracker.poll(&focus, 0.0, 0.25));
+        assert_eq!(tracker.action(), Some(&AppAction::Disable));
+        // Within the interval the provider is not asked again
+        focus.focus("com.apple.Safari", "Safari");
+        assert!(!tracker.poll(&focus, 0.1, 0.25));
+        assert_eq!(focus.queries.get(), 1);
+        assert!(tracker.poll(&focus, 0.3, 0.25));
+        assert_eq!(
+            tracker.action(),
+            Some(&AppAction::Profile("long".to_string()))
+        );
+        assert!(!tracker.poll(&focus, 0.6, 0.25));
+        focus.focus("com.apple.Terminal", "Terminal");
+        assert!(tracker.poll(&focus, 0.9, 0.25));
+        assert_eq!(tracker.action(), None);
+    }
+
+    fn rect(x: f64, y: f64, width: f64, height: f64) -> Rect {
+        Rect {
+            origin: Point { x, y },
+            size: Size { width, height },
+        }
+    }
+
+    #[test]
+    fn test_snap_point_picks_nearest_target() {
+        let targets = [rect(100.0, 0.0, 20.0, 20.0), rect(0.0, 130.0, 40.0, 10.0)];
+        let at = |x, y| snap::snap_point(Point { x, y }, &targets, 40.0);
+        // Just inside the nearest edge of the closer rectangle
+        assert_eq!(at(80.0, 10.0), Some(Point { x: 102.0, y: 10.0 }));
+        assert_eq!(at(20.0, 100.0), Some(Point { x: 20.0, y: 132.0 }));
+        assert_eq!(at(110.0, 10.0), None);
+        assert_eq!(at(300.0, 300.0), None);
+    }
+
+    #[test]
+    fn test_attraction_converges_without_overshoot() {
+        let target = Point { x: 40.0, y: -10.0 };
+        let mut position = Point { x: 0.0, y: 0.0 };
+        let mut velocity = Vector {
+            dx: 150.0,
+            dy: 60.0,
+        };
+        for _ in 0..400 {
+            velocity = snap::attract(position, velocity, target, 20.0, 0.005);
+            position.x += velocity.dx * 0.005;
+            position.y += velocity.dy * 0.005;
+            assert!(position.x <= target.x + 1.0);
+        }
+        assert!(snap::distance(position, target) < 0.5);
+    }
+
+    // Flicks right and lets the glide run out, returns where it stopped
+    fn glide_end(targets: Vec<Rect>) -> Point {
+        let mut engine = Engine::with_cursor_sink(Box::new(RecordingSink::default()));
+        engine.set_target_provider(Some(Box::new(StaticTargets(targets))));
+        let release = Point { x: 530.0, y: 500.0 };
+        flick(&mut engine, Point { x: 500.0, y: 500.0 }, release);
+        let mut ticks = 0;
+        while engine.is_gliding() {
+            engine.handle_no_touch(release, 0.005, false);
+            ticks += 1;
+            assert!(ticks < 2000);
+        }
+        engine.position()
+    }
+
+    #[test]
+    fn test_magnetic_end_lands_on_target() {
+        let natural = {
+            let _config = test_config(|_| {});
+            glide_end(Vec::new())
+        };
+        let _config = test_config(|config| config.magnetic_end = true);
+        // Nothing in range behaves like a plain glide
+        assert_eq!(glide_end(Vec::new()), natural);
+
+        // Stopping short of a button and overshooting one both end on it
+        for target in [
+            rect(natural.x + 15.0, natural.y - 10.0, 20.0, 20.0),
+            rect(natural.x - 50.0, natural.y + 20.0, 30.0, 16.0),
+        ] {
+            let end = glide_end(vec![target]);
+            let inside = snap::nearest_point_on_rect(&target, end);
+            assert!(
+                snap::distance(inside, end) < 0.5,
+                "{:?} ended at {:?}",
+                target,
+                end
+            );
+        }
+    }
+
+    #[test]
+    fn test_magnetic_end_reasons() {
+        let natural = {
+            let _config = test_config(|_| {});
+            glide_end(Vec::new())
+        };
+        let target = rect(natural.x + 15.0, natural.y - 10.0, 20.0, 20.0);
+        let end_reason = |strength| {
+            let _config = test_config(|config| {
+                config.magnetic_end = true;
+                config.magnetic_strength = strength;
+            });
+            let mut engine = Engine::with_cursor_sink(Box::new(RecordingSink::default()));
+            engine.set_target_provider(Some(Box::new(StaticTargets(vec![target]))));
+            let events = engine.event_channel();
+            let release = Point { x: 530.0, y: 500.0 };
+            flick(&mut engine, Point { x: 500.0, y: 500.0 }, release);
+            while engine.is_gliding() {
+                engine.handle_no_touch(release, 0.005, false);
+            }
+            events.try_iter().find_map(|event| match event {
+                EngineEvent::GlideEnd { reason, .. } => Some(reason),
+                _ => None,
+            })
+        };
+        assert_eq!(
+            end_reason(Config::defaults().magnetic_strength),
+            Some(GlideEndReason::Settled)
+        );
+        // Too weak a pull to get there before the magnet gives up
+        assert_eq!(end_reason(0.01), Some(GlideEndReason::TimedOut));
+    }
+
+    #[test]
+    fn test_direction_locking() {
+        let velocity = |degrees: f64| {
+            let radians = degrees.to_radians();
+            Vector {
+                dx: 1000.0 * radians.cos(),
+                dy: 1000.0 * radians.sin(),
+            }
+        };
+        // Near an axis the orthogonal component is dropped
+        let locked = lock_direction(velocity(184.0), 6.0, false);
+        assert_eq!(locked.dy, 0.0);
+        assert!((locked.dx + 1000.0 * 4f64.to_radians().cos()).abs() < 1e-9);
+        let locked = lock_direction(velocity(-87.0), 6.0, false);
+        assert_eq!(locked.dx, 0.0);
+        assert!(locked.dy < -990.0);
+        // Outside the tolerance, or on a diagonal without diagonal snapping, nothing changes
+        assert_eq!(lock_direction(velocity(20.0), 6.0, false), velocity(20.0));
+        assert_eq!(lock_direction(velocity(47.0), 6.0, false), velocity(47.0));
+        let locked = lock_direction(velocity(132.0), 6.0, true);
+        assert!((locked.dx + locked.dy).abs() < 1e-9);
+        assert!(locked.dy > 0.0);
+        assert_eq!(
+            lock_direction(Vector { dx: 0.0, dy: 0.0 }, 6.0, true).dx,
+            0.0
+        );
+    }
+
+    #[test]
+    fn test_axis_lock_keeps_glide_straight() {
+        let _config = test_config(|config| config.axis_lock_degrees = 8.0);
+        let mut engine = Engine::with_cursor_sink(Box::new(RecordingSink::default()));
+        let release = Point { x: 530.0, y: 502.0 };
+        flick(&mut engine, Point { x: 500.0, y: 500.0 }, release);
+        assert!(engine.is_gliding());
+        while engine.is_gliding() {
+            engine.handle_no_touch(release, 0.005, false);
+        }
+        assert_eq!(engine.position().y, release.y);
+        assert!(engine.position().x > 1000.0);
+
+        // 7.6 degrees off the axis, fast enough to glide only until the lock drops the vertical part
+        let near_threshold = |axis_lock_degrees: f64| {
+            let mut tuned = Config::clone(&config());
+            tuned.minimum_glide_velocity = 603.0;
+            tuned.axis_lock_degrees = axis_lock_degrees;
+            set_config(tuned);
+            let mut engine = Engine::with_cursor_sink(Box::new(RecordingSink::default()));
+            let start = Point { x: 500.0, y: 500.0 };
+            flick(&mut engine, start, Point { x: 503.0, y: 500.4 });
+            engine.is_gliding()
+        };
+        assert!(near_threshold(0.0));
+        assert!(!near_threshold(8.0));
+    }
+
+    #[test]
+    fn test_angular_velocity_estimate() {
+        // Quarter of a circle per second, sampled every 8 ms
+        let arc = |omega: f64| -> Vec<(f64, Point)> {
+            (0..10)
+                .map(|i| {
+                    let time = i as f64 * 0.008;
+                    let angle = omega * time;
+                    (
+                        time,
+                        Point {
+                            x: 0.5 + 0.2 * angle.cos(),
+                            y: 0.5 + 0.2 * angle.sin(),
+                        },
+                    )
+                })
+                .collect()
+        };
+        let omega = std::f64::consts::FRAC_PI_2;
+        assert!((spin::estimate_angular_velocity(&arc(omega)) - omega).abs() < 1e-6);
+        assert!((spin::estimate_angular_velocity(&arc(-omega)) + omega).abs() < 1e-6);
+
+        let straight: Vec<(f64, Point)> = (0..10)
+            .map(|i| {
+                (
+                    i as f64 * 0.008,
+                    Point {
+                        x: 0.1 * i as f64,
+                        y: 0.3,
+                    },
+                )
+            })
+            .collect();
+        assert_eq!(spin::estimate_angular_velocity(&straight), 0.0);
+        // A resting finger, or a single sample, has no direction at all
+        assert_eq!(
+            spin::estimate_angular_velocity(&[(0.0, Point { x: 0.5, y: 0.5 }); 4]),
+            0.0
+        );
+        assert_eq!(spin::estimate_angular_velocity(&straight[..1]), 0.0);
+    }
+
+    // Flicks right with a counter-clockwise spin, returns where the glide ended
+    fn spinning_glide_end(engine: &mut Engine) -> Point {
+        let release = Point { x: 530.0, y: 500.0 };
+        engine.update_engine_state(Point { x: 500.0, y: 500.0 });
+        engine.begin_touch(Point { x: 500.0, y: 500.0 });
+        engine.set_angular_velocity(2.0);
+        engine.handle_touch(release, 0.005, None);
+        engine.handle_no_touch(release, 0.005, false);
+        while engine.is_gliding() {
+            engine.handle_no_touch(release, 0.005, false);
+        }
+        engine.position()
+    }
+
+    #[test]
+    fn test_curved_glides() {
+        let straight = {
+            let _config = test_config(|_| {});
+            spinning_glide_end(&mut Engine::with_cursor_sink(Box::new(
+                RecordingSink::default(),
+            )))
+        };
+        assert_eq!(straight.y, 500.0);
+
+        let _config = test_config(|config| config.curved_glides = true);
+        let curved = spinning_glide_end(&mut Engine::with_cursor_sink(Box::new(
+            RecordingSink::default(),
+        )));
+        // Bends to the left of the flick direction and covers about the same path length
+        assert!(curved.y > 600.0);
+        assert!(curved.x < straight.x);
+        assert!(snap::distance(curved, Point { x: 530.0, y: 500.0 }) > 500.0);
+    }
+
+    #[test]
+    fn test_acceleration_curve() {
+        let curve: AccelerationCurve = "0:0.5, 100:1, 300:2".parse().unwrap();
+        assert_eq!(curve.gain(0.0), 0.5);
+        assert_eq!(curve.gain(50.0), 0.75);
+        assert_eq!(curve.gain(200.0), 1.5);
+        assert_eq!(curve.gain(1000.0), 2.0);
+        assert_eq!(
+            curve.to_string().parse::<AccelerationCurve>().unwrap(),
+            curve
+        );
+
+        let trackball: AccelerationCurve = "trackball".parse().unwrap();
+        assert_eq!(trackball.to_string(), "trackball");
+        assert!("off".parse::<AccelerationCurve>().unwrap().is_off());
+        assert_eq!(AccelerationCurve::off().gain(500.0), 1.0);
+
+        // Speeds out of order, a slower output at a higher input, and nonsense are all rejected
+        assert!("100:1,50:2".parse::<AccelerationCurve>().is_err());
+        assert!("0:2,100:0.1".parse::<AccelerationCurve>().is_err());
+        assert!("0:0".parse::<AccelerationCurve>().is_err());
+        assert!("fastest".parse::<AccelerationCurve>().is_err());
+        // A falling gain is fine as long as the output speed keeps rising
+        assert!("0:2,100:1.5".parse::<AccelerationCurve>().is_ok());
+
+        let mut config = Config::defaults();
+        assert!(config.set("acceleration_curve", "0:1,10:0").is_err());
+        config.set("acceleration_curve", "precise").unwrap();
+        assert_eq!(config.get("acceleration_curve").unwrap(), "precise");
+    }
+
+    #[test]
+    fn test_acceleration_curve_owns_pointer_while_touching() {
+        let _config = test_config(|config| {
+            config.acceleration_curve = "0:2".parse().unwrap();
+            config.trackpad_velocity_gain = 1.0;
+        });
+        let sink = RecordingSink::default();
+        let mut engine = Engine::with_cursor_sink(Box::new(sink.clone()));
+        engine.update_desktop_bounds(rect(0.0, 0.0, 1000.0, 1000.0));
+        let start = Point { x: 500.0, y: 500.0 };
+        engine.update_engine_state(start);
+        engine.begin_touch(start);
+        for step in 0..4 {
+            engine.set_touch_centroid(
+                Some(Point {
+                    x: 0.5 + 0.01 * step as f64,
+                    y: 0.5,
+                }),
+                1,
+            );
+            // The OS no longer moves the cursor, so the physical position stays where Lapsus put it
+            engine.handle_touch(engine.position(), 0.01, None);
+            assert!(sink.owned.get());
+        }
+        // Three 10 px raw moves at 1000 px/s, doubled by the curve
+        assert_eq!(engine.position(), Point { x: 560.0, y: 500.0 });
+        let moved: f64 = sink
+            .moves
+            .borrow()
+            .iter()
+            .map(|(_, _, delta)| delta.dx)
+            .sum();
+        assert_eq!(moved, 60.0);
+
+        engine.handle_no_touch(engine.position(), 0.01, false);
+        assert!(!sink.owned.get());
+    }
+
+    #[test]
+    fn test_acceleration_curve_leaves_gestures_to_the_os() {
+        let _config = test_config(|config| {
+            config.acceleration_curve = "0:2".parse().unwrap();
+            config.trackpad_velocity_gain = 1.0;
+        });
+        let sink = RecordingSink::default();
+        let mut engine = Engine::with_cursor_sink(Box::new(sink.clone()));
+        engine.update_desktop_bounds(rect(0.0, 0.0, 1000.0, 1000.0));
+        let start = Point { x: 500.0, y: 500.0 };
+        engine.update_engine_state(start);
+        engine.begin_touch(start);
+        let centroid = |x| Some(Point { x, y: 0.5 });
+        engine.set_touch_centroid(centroid(0.5), 1);
+        engine.handle_touch(engine.position(), 0.01, None);
+        assert!(sink.owned.get());
+        let moves = sink.moves.borrow().len();
+
+        // A second finger lands halfway across the trackpad and the two scroll together
+        for x in [0.75, 0.77, 0.79] {
+            engine.set_touch_centroid(centroid(x), 2);
+            engine.handle_touch(engine.position(), 0.01, None);
+            assert!(!sink.owned.get());
+        }
+        assert_eq!(sink.moves.borrow().len(), moves);
+        assert_eq!(engine.position(), start);
+
+        // Lifting it again picks up from where the remaining finger is, without a jump
+        engine.set_touch_centroid(centroid(0.6), 1);
+        engine.handle_touch(engine.position(), 0.01, None);
+        assert!(sink.owned.get());
+        assert_eq!(engine.position(), start);
+        engine.set_touch_centroid(centroid(0.61), 1);
+        engine.handle_touch(engine.position(), 0.01, None);
+        assert_eq!(engine.position(), Point { x: 520.0, y: 500.0 });
+    }
+
+    #[test]
+    fn test_acceleration_curve_drags_with_a_held_button() {
+        let _config = test_config(|config| {
+            config.acceleration_curve = "0:2".parse().unwrap();
+            config.drag_glide_mode = DragGlideMode::Warp;
+        });
+        let sink = RecordingSink::default();
+        let mut engine = Engine::with_cursor_sink(Box::new(sink.clone()));
+        engine.update_desktop_bounds(rect(0.0, 0.0, 1000.0, 1000.0));
+        let start = Point { x: 500.0, y: 500.0 };
+        engine.update_engine_state(start);
+        engine.set_pressed_button(Some(MouseButton::Left));
+        engine.begin_touch(start);
+        for step in 0..4 {
+            engine.set_touch_centroid(
+                Some(Point {
+                    x: 0.5 + 0.01 * step as f64,
+                    y: 0.5,
+                }),
+                1,
+            );
+            engine.handle_touch(engine.position(), 0.01, None);
+        }
+        let moves = sink.moves.borrow();
+        assert_eq!(moves.len(), 4);
+        assert!(
+            moves
+                .iter()
+                .all(|(emitted, _, _)| *emitted == Emitted::Drag(MouseButton::Left))
+        );
+    }
+
+    #[test]
+    fn test_velocity_blending() {
+        let pointer = Vector { dx: 100.0, dy: 0.0 };
+        let trackpad = Vector { dx: 300.0, dy: 0.0 };
+        let blend = |mode, trackpad| blend_velocity(mode, 0.25, pointer, trackpad);
+
+        assert_eq!(
+            blend(VelocityBlend::Max, Some(trackpad)),
+            (trackpad, VelocitySource::Trackpad)
+        );
+        assert_eq!(
+            blend(VelocityBlend::Pointer, Some(trackpad)),
+            (pointer, VelocitySource::Pointer)
+        );
+        assert_eq!(
+            blend(VelocityBlend::Trackpad, Some(trackpad)),
+            (trackpad, VelocitySource::Trackpad)
+        );
+        assert_eq!(
+            blend(VelocityBlend::Weighted, Some(trackpad)),
+            (Vector { dx: 150.0, dy: 0.0 }, VelocitySource::Blend(0.25))
+        );
+        // Without a trackpad estimate every mode falls back to the pointer
+        assert_eq!(
+            blend(VelocityBlend::Trackpad, None),
+            (pointer, VelocitySource::Pointer)
+        );
+
+        // Agreeing signals get the configured mix, diverging ones lean on the trackpad
+        let (velocity, _) = blend(
+            VelocityBlend::Confidence,
+            Some(Vector { dx: 100.0, dy: 0.0 }),
+        );
+        assert_eq!(velocity, pointer);
+        let (_, source) = blend(VelocityBlend::Confidence, Some(trackpad));
+        let VelocitySource::Blend(share) = source else {
+            panic!("expected a blend, got {}", source);
+        };
+        // A third of the speed: agreement 1/3, share 1/3 * 0.25 + 2/3
+        assert!((share - 0.75).abs() < 1e-9);
+        let (velocity, source) = blend(
+            VelocityBlend::Confidence,
+            Some(Vector { dx: 0.0, dy: 80.0 }),
+        );
+        assert_eq!(velocity, Vector { dx: 0.0, dy: 80.0 });
+        assert_eq!(source, VelocitySource::Trackpad);
+
+        assert_eq!(
+            "confidence".parse::<VelocityBlend>().unwrap(),
+            VelocityBlend::Confidence
+        );
+        assert!("average".parse::<VelocityBlend>().is_err());
+    }
+
+    // Lifts off a finger whose trackpad velocity is far above the speed the cursor had slowed
+    // down to, returns how far each of the first glide ticks moved
+    fn first_glide_steps(ticks: usize) -> Vec<f64> {
+        let mut engine = Engine::with_cursor_sink(Box::new(RecordingSink::default()));
+        engine.update_desktop_bounds(rect(0.0, 0.0, 1000.0, 1000.0));
+        let start = Point { x: 500.0, y: 500.0 };
+        engine.update_engine_state(start);
+        engine.begin_touch(start);
+        let release = Point { x: 501.0, y: 500.0 };
+        engine.handle_touch(release, 0.005, Some(Vector { dx: 2.0, dy: 0.0 }));
+        let mut steps = Vec::new();
+        let mut previous = engine.position();
+        engine.handle_no_touch(release, 0.005, false);
+        for _ in 0..ticks {
+            steps.push(engine.position().x - previous.x);
+            previous = engine.position();
+            engine.handle_no_touch(release, 0.005, false);
+        }
+        steps
+    }
+
+    #[test]
+    fn test_glide_start_easing() {
+        let jolt = {
+            let _config = test_config(|config| {
+                config.glide_ease_time = 0.0;
+                config.trackpad_velocity_gain = 1.0;
+            });
+            first_glide_steps(1)[0]
+        };
+        assert!(jolt > 9.0);
+
+        let _config = test_config(|config| {
+            config.glide_ease_time = 0.03;
+            config.trackpad_velocity_gain = 1.0;
+        });
+        let steps = first_glide_steps(10);
+        // Starts near the 200 px/s the cursor was moving at and reaches the decaying glide in 30 ms
+        assert!(steps[0] > 1.0 && steps[0] < 2.0, "{:?}", steps);
+        assert!(steps.windows(2).take(5).all(|pair| pair[1] > pair[0]));
+        assert!(steps[5] > steps[6] && steps[5] > 8.0, "{:?}", steps);
+    }
+
+    #[test]
+    fn test_press_modulates_glide() {
+        let curve: PressCurve = "flick".parse().unwrap();
+        assert_eq!(curve.strength(0.0), 1.3);
+        assert_eq!(curve.strength(0.4), 1.0);
+        assert!((curve.strength(0.65) - 0.5).abs() < 1e-9);
+        assert_eq!(curve.strength(1.0), 0.0);
+        assert_eq!(PressCurve::off().strength(1.0), 1.0);
+        let custom: PressCurve = "0:1.2,1:0".parse().unwrap();
+        assert_eq!(custom.to_string(), "0:1.2,1:0");
+        assert!("1:1,0.5:1".parse::<PressCurve>().is_err());
+        assert!("0:-1".parse::<PressCurve>().is_err());
+        assert!("2:1".parse::<PressCurve>().is_err());
+
+        // The heaviest finger counts, and a full value of zero leaves that reading out
+        let contact = |size, pressure| Contact {
+            position: Point { x: 0.5, y: 0.5 },
+            size,
+            pressure,
+        };
+        let contacts = [contact(0.3, 0.0), contact(0.75, 50.0)];
+        assert_eq!(press::contact_weight(&contacts, 1.5, 0.0), 0.5);
+        assert_eq!(press::contact_weight(&contacts, 1.5, 25.0), 1.0);
+        assert_eq!(press::contact_weight(&[], 1.5, 25.0), 0.0);
+
+        // A press released with lighter fingers still counts as a press
+        let mut tracker = PressTracker::default();
+        tracker.update(1.0, 0.9, 0.1);
+        tracker.update(1.05, 0.2, 0.1);
+        assert_eq!(tracker.weight(), 0.9);
+        tracker.update(1.2, 0.2, 0.1);
+        assert_eq!(tracker.weight(), 0.2);
+
+        let _config = test_config(|config| {
+            config.glide_press = curve;
+            config.glide_ease_time = 0.0;
+            config.trackpad_velocity_gain = 1.0;
+        });
+        // Pressed down, then lifted with the weight already off the finger
+        let mut monitor = TrackpadMonitor::with_enumerator(Box::new(NoDevices));
+        let pressed = ContactFrame {
+            timestamp: 1.0,
+            contacts: vec![contact(1.5, 0.0)],
+        };
+        monitor.inject_frame(1, &pressed);
+        monitor.inject_frame(1, &touch_frame(1.01, 0.52));
+        monitor.inject_frame(
+            1,
+            &ContactFrame {
+                timestamp: 1.02,
+                contacts: Vec::new(),
+            },
+        );
+        let mut frames = Vec::new();
+        monitor.drain_frames(&mut frames);
+        let weights: Vec<f64> = frames.iter().map(|frame| frame.contact_weight).collect();
+        assert_eq!(weights, [1.0, 1.0, 1.0]);
+
+        // Trackpad velocity of 2000 px/s at release, scaled by the curve
+        let release_speed = |weight| {
+            let mut engine = Engine::with_cursor_sink(Box::new(RecordingSink::default()));
+            engine.update_desktop_bounds(rect(0.0, 0.0, 1000.0, 1000.0));
+            let start = Point { x: 500.0, y: 500.0 };
+            engine.update_engine_state(start);
+            engine.begin_touch(start);
+            let release = Point { x: 501.0, y: 500.0 };
+            engine.handle_touch(release, 0.005, Some(Vector { dx: 2.0, dy: 0.0 }));
+            engine.set_contact_weight(weight);
+            engine.handle_no_touch(release, 0.0, false);
+            (engine.velocity().dx, engine.is_gliding())
+        };
+        assert_eq!(release_speed(0.4), (2000.0, true));
+        assert_eq!(release_speed(0.0), (2600.0, true));
+        assert_eq!(release_speed(1.0), (0.0, false));
+    }
+
+    #[test]
+    fn test_glide_transitions() {
+        use GlideEvent::*;
+        use GlideState::*;
+        let run = |events: &[GlideEvent]| {
+            events
+                .iter()
+                .try_fold(Idle, |state, event| transition(state, *event))
+        };
+        assert_eq!(run(&[TouchStart, TouchEnd, Launch, Brake, Stop]), Ok(Idle));
+        assert_eq!(run(&[TouchStart, TouchEnd, Stop]), Ok(Idle));
+        assert_eq!(
+            run(&[TouchStart, TouchEnd, Launch, TouchStart]),
+            Ok(Touching)
+        );
+        assert_eq!(run(&[TouchStart, Suspend]), Ok(Suppressed));
+        assert_eq!(
+            run(&[TouchStart, TouchEnd, Launch, Suspend, Resume]),
+            Ok(Idle)
+        );
+
+        for (state, event) in [
+            (Idle, TouchEnd),
+            (Idle, Launch),
+            (Idle, Brake),
+            (Touching, TouchStart),
+            (Touching, Launch),
+            (Releasing, Brake),
+            (Gliding, TouchEnd),
+            (Braking, Brake),
+            (Suppressed, TouchStart),
+            (Suppressed, Suspend),
+            (Gliding, Resume),
+        ] {
+            assert_eq!(
+                transition(state, event),
+                Err(IllegalTransition { state, event })
+            );
+        }
+    }
+
+    fn recorded_transitions(engine: &mut Engine) -> Rc<RefCell<Vec<Transition>>> {
+        let transitions = Rc::new(RefCell::new(Vec::new()));
+        let recorded = transitions.clone();
+        engine.add_listener(Box::new(move |event| {
+            if let EngineEvent::Transition(transition) = event {
+                recorded.borrow_mut().push(*transition)
+            }
+        }));
+        transitions
+    }
+
+    #[test]
+    fn test_engine_reports_glide_transitions() {
+        let _config = test_config(|_| {});
+        let states = |transitions: &Rc<RefCell<Vec<Transition>>>| -> Vec<GlideState> {
+            transitions
+                .borrow()
+                .iter()
+                .map(|transition| transition.to)
+                .collect()
+        };
+
+        let mut engine = Engine::with_cursor_sink(Box::new(RecordingSink::default()));
+        let transitions = recorded_transitions(&mut engine);
+        let release = Point { x: 530.0, y: 500.0 };
+        flick(&mut engine, Point { x: 500.0, y: 500.0 }, release);
+        while engine.is_gliding() {
+            engine.handle_no_touch(release, 0.005, false);
+        }
+        use GlideState::*;
+        assert_eq!(
+            states(&transitions),
+            [Touching, Releasing, Gliding, Braking, Idle]
+        );
+
+        // A slow release and a multi-finger release both settle without gliding
+        transitions.borrow_mut().clear();
+        flick(&mut engine, release, Point { x: 530.5, y: 500.0 });
+        let start = engine.position();
+        engine.handle_touch(start, 0.005, None);
+        engine.handle_no_touch(start, 0.005, true);
+        assert_eq!(
+            states(&transitions),
+            [Touching, Releasing, Idle, Touching, Releasing, Idle]
+        );
+
+        // Suspending mid-glide ends it, resuming starts over from idle
+        transitions.borrow_mut().clear();
+        flick(&mut engine, Point { x: 500.0, y: 500.0 }, release);
+        engine.suspend();
+        assert!(!engine.is_gliding());
+        engine.suspend();
+        engine.resume();
+        assert_eq!(engine.glide_state(), Idle);
+        assert_eq!(
+            states(&transitions),
+            [Touching, Releasing, Gliding, Suppressed, Idle]
+        );
+    }
+
+    #[test]
+    fn test_engine_events() {
+        let _config = test_config(|_| {});
+        let mut engine = Engine::with_cursor_sink(Box::new(RecordingSink::default()));
+        let events = Rc::new(RefCell::new(Vec::new()));
+        let recorded = events.clone();
+        engine.add_listener(Box::new(move |event| recorded.borrow_mut().push(*event)));
+        let channel = engine.event_channel();
+
+        let start = Point { x: 500.0, y: 500.0 };
+        let release = Point { x: 530.0, y: 500.0 };
+        flick(&mut engine, start, release);
+        while engine.is_gliding() {
+            engine.handle_no_touch(release, 0.005, false);
+        }
+        let is_transition = |event: &EngineEvent| matches!(event, EngineEvent::Transition(_));
+        let events: Vec<EngineEvent> = events
+            .borrow()
+            .iter()
+            .filter(|event| !is_transition(event))
+            .copied()
+            .collect();
+        assert_eq!(events[0], EngineEvent::TouchBegin { position: start });
+        assert!(matches!(events[1], EngineEvent::TouchEnd { position, .. } if position == release));
+        let EngineEvent::GlideStart { velocity, .. } = events[2] else {
+            panic!("expected a glide start, got {:?}", events[2]);
+        };
+        assert_eq!(
+            velocity,
+            Vector {
+                dx: 6000.0,
+                dy: 0.0
+            }
+        );
+        let ticks = &events[3..events.len() - 1];
+        assert!(!ticks.is_empty());
+        assert!(
+            ticks
+                .iter()
+                .all(|event| matches!(event, EngineEvent::GlideTick { .. }))
+        );
+        let EngineEvent::GlideEnd {
+            position,
+            reason,
+            distance,
+        } = events[events.len() - 1]
+        else {
+            panic!("expected a glide end");
+        };
+        assert_eq!(reason, GlideEndReason::Stopped);
+        assert!((distance - (position.x - release.x)).abs() < 1e-6);
+        let sent: Vec<EngineEvent> = channel
+            .try_iter()
+            .filter(|event| !is_transition(event))
+            .collect();
+        assert_eq!(sent, events);
+
+        // A finger landing on a glide catches it, and a dropped receiver is simply forgotten
+        drop(channel);
+        let caught = engine.event_channel();
+        flick(&mut engine, start, release);
+        engine.begin_touch(engine.position());
+        let reasons: Vec<GlideEndReason> = caught
+            .try_iter()
+            .filter_map(|event| match event {
+                EngineEvent::GlideEnd { reason, .. } => Some(reason),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(reasons, [GlideEndReason::Caught]);
+    }
+
+    #[derive(Default)]
+    struct FakeTimer {
+        interval: Option<f64>,
+        next: f64,
+        now: f64,
+    }
+
+    impl TickTimer for FakeTimer {
+        fn start(&mut self, interval: f64) {
+            self.interval = Some(interval);
+            self.next = self.now + interval;
+        }
+
+        fn stop(&mut self) {
+            self.interval = None;
+        }
+    }
+
+    impl FakeTimer {
+        // Fires every tick due before `until`, returning how many fired
+        fn run_until(
+            &mut self,
+            scheduler: &mut Scheduler,
+            policy: &TickPolicy,
+            until: f64,
+            busy: impl Fn(f64) -> bool,
+        ) -> usize {
+            let mut ticks = 0;
+            while let Some(interval) = self.interval {
+                if self.next >= until {
+                    break;
+                }
+                let now = self.next;
+                self.now = now;
+                self.next += interval;
+                ticks += 1;
+                scheduler.after_tick(self, policy, busy(now), now);
+            }
+            self.now = until;
+            ticks
+        }
+    }
+
+    #[test]
+    fn test_scheduler_sleeps_while_idle() {
+        let policy = TickPolicy {
+            active_interval: 0.005,
+            idle_interval: 0.0,
+            idle_delay: 0.5,
+        };
+        let notified = Arc::new(AtomicUsize::new(0));
+        let counter = notified.clone();
+        let waker = Waker::new(move || {
+            counter.fetch_add(1, Ordering::SeqCst);
+        });
+        let mut scheduler = Scheduler::new(waker.clone());
+        let mut timer = FakeTimer::default();
+
+        // Launch ticks briefly, then goes quiet for a minute
+        assert!(scheduler.wake(&mut timer, &policy, 0.0));
+        let ticks = timer.run_until(&mut scheduler, &policy, 1.0, |_| false);
+        assert!((95..=101).contains(&ticks), "{} ticks", ticks);
+        assert_eq!(scheduler.mode(), TickMode::Idle);
+        assert_eq!(timer.interval, None);
+        assert_eq!(timer.run_until(&mut scheduler, &policy, 60.0, |_| false), 0);
+
+        // Only the first frame of a touch gets through to the main thread
+        for _ in 0..10 {
+            waker.wake();
+        }
+        assert_eq!(notified.load(Ordering::SeqCst), 1);
+        assert!(scheduler.wake(&mut timer, &policy, 60.0));
+        assert_eq!(scheduler.mode(), TickMode::Active);
+
+        // 0.2s of touch and a 1s glide run at full rate, then the idle delay runs out
+        let busy = |now: f64| now < 61.2;
+        let ticks = timer.run_until(&mut scheduler, &policy, 61.2, busy);
+        assert!((238..=240).contains(&ticks), "{} ticks", ticks);
+        let ticks = timer.run_until(&mut scheduler, &policy, 120.0, busy);
+        assert!((99..=101).contains(&ticks), "{} ticks", ticks);
+        assert_eq!(scheduler.mode(), TickMode::Idle);
+        assert_eq!(timer.interval, None);
+
+        // Waking an active scheduler changes nothing, and a slow idle rate keeps ticking
+        let slow = TickPolicy {
+            idle_interval: 1.0,
+            ..policy
+        };
+        assert!(scheduler.wake(&mut timer, &slow, 120.0));
+        assert!(!scheduler.wake(&mut timer, &slow, 120.0));
+        assert_eq!(notified.load(Ordering::SeqCst), 1);
+        let ticks = timer.run_until(&mut scheduler, &slow, 180.0, |_| false);
+        assert!((150..=160).contains(&ticks), "{} ticks", ticks);
+        assert_eq!(timer.interval, Some(1.0));
+        waker.wake();
+        assert_eq!(notified.load(Ordering::SeqCst), 2);
+    }
+
+    #[test]
+    fn test_time_base() {
+        let mut time = TimeBase::new();
+        // Frames run 1000s ahead of the wall clock, and every tick integrates only new frames
+        time.observe(Some(1000.0), 0.0);
+        assert_eq!(time.touch_delta(0.001, 0.05), Some(0.001));
+        assert_eq!(time.touch_delta(0.001, 0.05), None);
+        time.observe(Some(1000.008), 0.009);
+        assert!((time.touch_delta(0.001, 0.05).unwrap() - 0.008).abs() < 1e-9);
+        time.observe(Some(1000.5), 0.5);
+        assert_eq!(time.touch_delta(0.001, 0.05), Some(0.05));
+
+        // The glide starts at the lift-off frame and catches up in whole steps
+        time.observe(Some(1000.6), 0.6);
+        assert_eq!(time.glide_steps(0.6, 0.004, 0.05), 0);
+        assert_eq!(time.glide_steps(0.61, 0.004, 0.05), 2);
+        assert_eq!(time.glide_steps(0.613, 0.004, 0.05), 1);
+        // A one second stall only catches up `max_catch_up`
+        assert_eq!(time.glide_steps(1.613, 0.004, 0.05), 12);
+        assert_eq!(time.glide_steps(1.617, 0.004, 0.05), 1);
+        time.reset();
+        assert_eq!(time.glide_steps(2.0, 0.004, 0.05), 12);
+    }
+
+    #[test]
+    fn test_glide_does_not_depend_on_tick_timing() {
+        let _config = test_config(|_| {});
+        // Final position, total steps and the most steps in one tick
+        let glide = |ticks: &mut dyn FnMut() -> f64| -> (Point, u32, u32) {
+            let mut engine = Engine::with_cursor_sink(Box::new(RecordingSink::default()));
+            let mut time = TimeBase::new();
+            let start = Point { x: 500.0, y: 500.0 };
+            let release = Point { x: 530.0, y: 510.0 };
+            engine.update_engine_state(start);
+            engine.begin_touch(start);
+            engine.handle_touch(release, 0.005, None);
+            time.observe(Some(50.0), 10.0);
+            engine.handle_no_touch(release, 0.0, false);
+            assert!(engine.is_gliding());
+            let mut wall = 10.0;
+            let (mut steps, mut most) = (0, 0);
+            while engine.is_gliding() {
+                wall += ticks();
+                let due = time.glide_steps(wall, 0.004, 0.05);
+                steps += due;
+                most = most.max(due);
+                engine.step_glide(due, 0.004);
+            }
+            (engine.position(), steps, most)
+        };
+
+        let (steady, steady_steps, _) = glide(&mut || 0.005);
+        let mut rng = XorShift(42);
+        let (jittery, _, _) = glide(&mut || rng.range(0.001, 0.03));
+        assert!(steady_steps > 20);
+        assert!(
+            (steady.x - jittery.x).abs() < 1e-9,
+            "{:?} {:?}",
+            steady,
+            jittery
+        );
+        assert!(
+            (steady.y - jittery.y).abs() < 1e-9,
+            "{:?} {:?}",
+            steady,
+            jittery
+        );
+
+        // A hitch only pauses the glide, the cursor never jumps more than `max_catch_up` ahead
+        let mut tick = 0;
+        let (hitched, _, most) = glide(&mut || {
+            tick += 1;
+            if tick == 3 { 0.5 } else { 0.005 }
+        });
+        assert_eq!(most, 12);
+        assert!((steady.x - hitched.x).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_snapshot_reads_are_never_torn() {
+        const LAST: u64 = 200_000;
+        let (mut writer, mut reader) = snapshot_channel([0u64; 32]);
+        let producer = thread::spawn(move || {
+            for value in 1..=LAST {
+                writer.publish([value; 32]);
+            }
+        });
+        let mut previous = 0;
+        let mut reads = 0;
+        while previous < LAST {
+            let snapshot = reader.read();
+            assert!(
+                snapshot.iter().all(|&value| value == snapshot[0]),
+                "torn read {:?}",
+                snapshot
+            );
+            assert!(
+                snapshot[0] >= previous,
+                "went back from {} to {}",
+                previous,
+                snapshot[0]
+            );
+            previous = snapshot[0];
+            reads += 1;
+        }
+        producer.join().expect("producer panicked");
+        assert!(reads > 1);
+        // Nothing new keeps returning the last value
+        assert_eq!(reader.read(), [LAST; 32]);
+    }
+
+    fn touch_frame(timestamp: f64, x: f64) -> ContactFrame {
+        ContactFrame {
+            timestamp,
+            contacts: vec![Contact::at(Point { x, y: 0.5 })],
+        }
+    }
+
+    // Drains until `expected` frames came through the queue or were dropped
+    fn drain_replay(monitor: &mut TrackpadMonitor, expected: usize) -> Vec<TouchSnapshot> {
+        let mut drained = Vec::new();
+        let mut frames = Vec::new();
+        for _ in 0..500 {
+            thread::sleep(Duration::from_millis(2));
+            let done = drained.len() + monitor.dropped_frames() as usize >= expected;
+            monitor.drain_frames(&mut frames);
+            drained.extend_from_slice(&frames);
+            if done {
+                return drained;
+            }
+        }
+        panic!("replay did not finish");
+    }
+
+    #[test]
+    fn test_frame_queue_keeps_touch_edges() {
+        let _config = test_config(|_| {});
+        let mut monitor = TrackpadMonitor::with_enumerator(Box::new(NoDevices));
+        let mut frames = Vec::new();
+        monitor.drain_frames(&mut frames);
+        assert_eq!(frames, [TouchSnapshot::EMPTY]);
+
+        // A tap that begins and ends between two drains still shows both edges
+        let tap = vec![
+            touch_frame(1.0, 0.5),
+            touch_frame(1.0001, 0.5),
+            ContactFrame {
+                timestamp: 1.0002,
+                contacts: Vec::new(),
+            },
+        ];
+        monitor.replay(tap).expect("replay failed");
+        let drained = drain_replay(&mut monitor, 3);
+        let edges: Vec<bool> = drained
+            .iter()
+            .filter(|frame| frame.timestamp > 0.0)
+            .map(|frame| frame.is_touching)
+            .collect();
+        assert!(edges.starts_with(&[true, true, false]), "{:?}", edges);
+        assert_eq!(monitor.dropped_frames(), 0);
+
+        // Overflow drops the newest queued frames and counts them, the current state survives
+        thread::sleep(Duration::from_millis(20));
+        let burst: Vec<ContactFrame> = (0..FRAME_QUEUE_CAPACITY + 40)
+            .map(|index| touch_frame(2.0, 0.1 + index as f64 * 0.001))
+            .collect();
+        // Replay appends a lift-off frame after the burst
+        let total = burst.len() + 1;
+        monitor.replay(burst).expect("replay failed");
+        while (monitor.dropped_frames() as usize) < total - FRAME_QUEUE_CAPACITY {
+            thread::sleep(Duration::from_millis(2));
+        }
+        monitor.drain_frames(&mut frames);
+        assert_eq!(frames.len(), FRAME_QUEUE_CAPACITY + 1);
+        assert_eq!(
+            monitor.dropped_frames() as usize,
+            total - FRAME_QUEUE_CAPACITY
+        );
+        assert!(!frames[frames.len() - 1].is_touching);
+        assert!(frames[frames.len() - 2].is_touching);
+    }
+
+    #[test]
+    fn test_device_rules() {
+        let rules = DeviceRules::parse(
+            "# trackpads\n\
+             builtin gain 0.8\n\
+             external profile precise\n\
+             family:128 gain 1.5 size 160x115\n\
+             id:0x2a gain 1.2 profile fast\n",
+        )
+        .expect("rules should parse");
+        let device = |id, family, builtin| DeviceInfo {
+            id,
+            family,
+            builtin,
+            width_mm: 160.0,
+            height_mm: 115.0,
+        };
+        assert_eq!(
+            rules.settings_for(&device(1, 98, true)),
+            DeviceSettings {
+                gain: 0.8,
+                profile: None,
+                size_mm: None
+            }
+        );
+        assert_eq!(
+            rules.settings_for(&device(2, 128, false)),
+            DeviceSettings {
+                gain: 1.5,
+                profile: Some("precise".to_string()),
+                size_mm: Some((160.0, 115.0))
+            }
+        );
+        // Later lines win
+        assert_eq!(
+            rules.settings_for(&device(42, 128, false)),
+            DeviceSettings {
+                gain: 1.2,
+                profile: Some("fast".to_string()),
+                size_mm: Some((160.0, 115.0))
+            }
+        );
+        assert_eq!(DeviceMatcher::parse("id:42"), Ok(DeviceMatcher::Id(42)));
+        assert!(DeviceRules::parse("builtin").is_err());
+        assert!(DeviceRules::parse("builtin gain").is_err());
+        assert!(DeviceRules::parse("builtin gain -1").is_err());
+        assert!(DeviceRules::parse("usb gain 1").is_err());
+        assert!(DeviceRules::parse("external speed 2").is_err());
+        assert!(DeviceRules::parse("external size 160").is_err());
+        assert!(DeviceRules::parse("external size 0x100").is_err());
+    }
+
+    #[test]
+    fn test_devices_do_not_mix_touches() {
+        let _config = test_config(|_| {});
+        let mut monitor = TrackpadMonitor::with_enumerator(Box::new(NoDevices));
+        let lift = |timestamp| ContactFrame {
+            timestamp,
+            contacts: Vec::new(),
+        };
+        monitor.inject_frame(1, &touch_frame(1.0, 0.2));
+        assert_eq!(monitor.snapshot().device, Some(1));
+
+        // The most recent touch takes over, and the other device no longer moves anything
+        monitor.inject_frame(2, &touch_frame(1.01, 0.8));
+        monitor.inject_frame(1, &touch_frame(1.02, 0.3));
+        let snapshot = monitor.snapshot();
+        assert_eq!(snapshot.device, Some(2));
+        assert_eq!(snapshot.centroid, Some(Point { x: 0.8, y: 0.5 }));
+        assert_eq!(snapshot.timestamp, 1.01);
+
+        // Each device keeps its own history, so switching back does not jump between them
+        monitor.inject_frame(2, &lift(1.03));
+        monitor.inject_frame(1, &lift(1.04));
+        monitor.inject_frame(1, &touch_frame(1.05, 0.4));
+        monitor.inject_frame(1, &touch_frame(1.06, 0.41));
+        let snapshot = monitor.snapshot();
+        assert_eq!(snapshot.device, Some(1));
+        assert!(snapshot.normalized_velocity.dx > 0.0);
+        assert!(snapshot.normalized_velocity.dx < 1.0, "{:?}", snapshot);
+        let mut frames = Vec::new();
+        monitor.drain_frames(&mut frames);
+        let devices: Vec<Option<u64>> = frames.iter().map(|frame| frame.device).collect();
+        assert_eq!(devices, [Some(1), Some(2), Some(2), Some(1), Some(1)]);
+    }
+
+    type ChangeSlot = Arc<Mutex<Option<Box<dyn Fn() + Send + Sync>>>>;
+
+    // Devices come and go by editing `present`, and every start and stop is logged. With
+    // `watchable` set, changes are only noticed after `notify`
+    #[derive(Clone, Default)]
+    struct FakeDevices {
+        present: Arc<Mutex<Vec<u64>>>,
+        log: Arc<Mutex<Vec<(u64, bool)>>>,
+        callbacks: Arc<Mutex<HashMap<u64, FrameCallback>>>,
+        watchable: bool,
+        on_change: ChangeSlot,
+    }
+
+    impl FakeDevices {
+        fn set_present(&self, ids: &[u64]) {
+            *self.present.lock().unwrap() = ids.to_vec();
+        }
+
+        fn info(id: u64) -> DeviceInfo {
+            DeviceInfo {
+                id,
+                family: 0,
+                builtin: id == 1,
+                width_mm: if id == 1 { 100.0 } else { 160.0 },
+                height_mm: if id == 1 { 80.0 } else { 115.0 },
+            }
+        }
+
+        fn send(&self, id: u64, frame: &ContactFrame) {
+            let callbacks = self.callbacks.lock().unwrap();
+            callbacks[&id](&mut frame.contacts.iter().copied(), frame.timestamp);
+        }
+
+        fn notify(&self) {
+            if let Some(on_change) = self.on_change.lock().unwrap().as_ref() {
+                on_change();
+            }
+        }
+    }
+
+    // Stops the notifications when the monitor drops it
+    struct FakeWatch(ChangeSlot);
+
+    impl Drop for FakeWatch {
+        fn drop(&mut self) {
+            *self.0.lock().unwrap() = None;
+        }
+    }
+
+    struct FakeDevice {
+        id: u64,
+        devices: FakeDevices,
+    }
+
+    impl TouchDevice for FakeDevice {
+        fn info(&self) -> DeviceInfo {
+            FakeDevices::info(self.id)
+        }
+
+        fn start(&mut self, on_frame: FrameCallback) {
+            self.devices.log.lock().unwrap().push((self.id, true));
+            self.devices
+                .callbacks
+                .lock()
+                .unwrap()
+                .insert(self.id, on_frame);
+        }
+
+        fn stop(&mut self) {
+            self.devices.log.lock().unwrap().push((self.id, false));
+            self.devices.callbacks.lock().unwrap().remove(&self.id);
+        }
+    }
+
+    impl DeviceEnumerator for FakeDevices {
+        fn enumerate(&mut self) -> Vec<Box<dyn TouchDevice>> {
+            let present = self.present.lock().unwrap().clone();
+            present
+                .into_iter()
+                .map(|id| {
+                    Box::new(FakeDevice {
+                        id,
+                        devices: self.clone(),
+                    }) as Box<dyn TouchDevice>
+                })
+                .collect()
+        }
+
+        fn watch(&mut self, on_change: Box<dyn Fn() + Send + Sync>) -> Option<DeviceWatch> {
+            if !self.watchable {
+                return None;
+            }
+            *self.on_change.lock().unwrap() = Some(on_change);
+            Some(Box::new(FakeWatch(self.on_change.clone())))
+        }
+    }
+
+    #[test]
+    fn test_device_registry_diffs_devices() {
+        let mut fake = FakeDevices::default();
+        let mut registry = DeviceRegistry::default();
+        let connect = |_: &DeviceInfo| -> FrameCallback { Box::new(|_, _| {}) };
+        use DeviceChange::*;
+
+        fake.set_present(&[1]);
+        assert_eq!(
+            registry.refresh(&mut fake, connect),
+            [Added(FakeDevices::info(1))]
+        );
+        fake.set_present(&[1, 2]);
+        assert_eq!(
+            registry.refresh(&mut fake, connect),
+            [Added(FakeDevices::info(2))]
+        );
+        assert_eq!(registry.refresh(&mut fake, connect), []);
+        fake.set_present(&[2]);
+        assert_eq!(
+            registry.refresh(&mut fake, connect),
+            [Removed(FakeDevices::info(1))]
+        );
+        // Unplugged and plugged back in between two polls is a new registration
+        fake.set_present(&[3, 2]);
+        assert_eq!(
+            registry.refresh(&mut fake, connect),
+            [Added(FakeDevices::info(3))]
+        );
+        assert_eq!(
+            registry.devices(),
+            [FakeDevices::info(2), FakeDevices::info(3)]
+        );
+        assert_eq!(
+            registry.stop_all(),
+            [Removed(FakeDevices::info(2)), Removed(FakeDevices::info(3))]
+        );
+        assert_eq!(
+            *fake.log.lock().unwrap(),
+            [
+                (1, true),
+                (2, true),
+                (1, false),
+                (3, true),
+                (2, false),
+                (3, false)
+            ]
+        );
+    }
+
+    #[test]
+    fn test_monitor_follows_hot_plug() {
+        let _config = test_config(|config| config.device_poll_interval = 0.005);
+        let fake = FakeDevices::default();
+        fake.set_present(&[1]);
+        let mut monitor = TrackpadMonitor::with_enumerator(Box::new(fake.clone()));
+        let events = monitor.device_events();
+        let next_event = || {
+            events
+                .recv_timeout(Duration::from_secs(2))
+                .expect("no device event")
+        };
+        monitor.start();
+        assert_eq!(next_event(), DeviceChange::Added(FakeDevices::info(1)));
+        assert_eq!(monitor.devices(), [FakeDevices::info(1)]);
+
+        fake.set_present(&[1, 2]);
+        assert_eq!(next_event(), DeviceChange::Added(FakeDevices::info(2)));
+        fake.send(2, &touch_frame(1.0, 0.5));
+        let snapshot = monitor.snapshot();
+        assert_eq!(snapshot.device, Some(2));
+        assert!(snapshot.is_touching);
+
+        // Unplugged mid-touch: the fingers lift and the device is forgotten
+        fake.set_present(&[1]);
+        assert_eq!(next_event(), DeviceChange::Removed(FakeDevices::info(2)));
+        let snapshot = monitor.snapshot();
+        assert_eq!(snapshot.device, Some(2));
+        assert!(!snapshot.is_touching);
+        assert_eq!(monitor.device(2), None);
+
+        monitor.stop();
+        assert_eq!(next_event(), DeviceChange::Removed(FakeDevices::info(1)));
+        assert!(monitor.devices().is_empty());
+        assert_eq!(fake.log.lock().unwrap().last(), Some(&(1, false)));
+        // The enumerator comes back, so the monitor can start again
+        monitor.start();
+        assert_eq!(next_event(), DeviceChange::Added(FakeDevices::info(1)));
+        monitor.stop();
+    }
+
+    #[test]
+    fn test_monitor_waits_for_device_notifications() {
+        // Polling would pick the new device up at once, notifications replace it
+        let _config = test_config(|config| config.device_poll_interval = 0.005);
+        let fake = FakeDevices {
+            watchable: true,
+            ..FakeDevices::default()
+        };
+        fake.set_present(&[1]);
+        let mut monitor = TrackpadMonitor::with_enumerator(Box::new(fake.clone()));
+        let events = monitor.device_events();
+        monitor.start();
+        let next_event = |timeout| events.recv_timeout(Duration::from_secs_f64(timeout));
+        assert_eq!(
+            next_event(2.0),
+            Ok(DeviceChange::Added(FakeDevices::info(1)))
+        );
+
+        fake.set_present(&[1, 2]);
+        assert!(next_event(0.1).is_err());
+        fake.notify();
+        assert_eq!(
+            next_event(2.0),
+            Ok(DeviceChange::Added(FakeDevices::info(2)))
+        );
+        // Already registered devices keep their handle
+        assert_eq!(*fake.log.lock().unwrap(), [(1, true), (2, true)]);
+
+        monitor.stop();
+        assert!(fake.on_change.lock().unwrap().is_none());
+    }
+
+    #[test]
+    fn test_physical_velocity() {
+        let _config = test_config(|config| {
+            config.physical_velocity = true;
+            config.pixels_per_mm = 10.0;
+            config.velocity_smoothing = 1.0;
+            config.velocity_blend = VelocityBlend::Trackpad;
+        });
+        let fake = FakeDevices::default();
+        fake.set_present(&[1, 2, 3]);
+        let mut monitor = TrackpadMonitor::with_enumerator(Box::new(fake.clone()));
+        monitor.set_device_rules(DeviceRules::parse("id:3 size 200x100").unwrap());
+        let events = monitor.device_events();
+        monitor.start();
+        for _ in 0..3 {
+            events
+                .recv_timeout(Duration::from_secs(2))
+                .expect("no device event");
+        }
+
+        // 2 mm in 10 ms on each trackpad, whatever its size
+        let mut flick = |id: u64, width_mm: f64| {
+            fake.send(id, &touch_frame(1.0, 0.3));
+            fake.send(id, &touch_frame(1.01, 0.3 + 2.0 / width_mm));
+            let snapshot = monitor.snapshot();
+            fake.send(
+                id,
+                &ContactFrame {
+                    timestamp: 1.02,
+                    contacts: Vec::new(),
+                },
+            );
+            assert_eq!(snapshot.device, Some(id));
+            snapshot.physical_velocity.expect("no physical velocity")
+        };
+        for (id, width_mm) in [(1, 100.0), (2, 160.0), (3, 200.0)] {
+            let velocity = flick(id, width_mm);
+            assert!((velocity.dx - 200.0).abs() < 1e-6, "{}: {:?}", id, velocity);
+            assert_eq!(velocity.dy, 0.0);
+        }
+        monitor.stop();
+
+        // mm/s map to pixels regardless of the desktop size
+        for width in [1000.0, 3000.0] {
+            let mut engine = Engine::with_cursor_sink(Box::new(RecordingSink::default()));
+            engine.update_desktop_bounds(rect(0.0, 0.0, width, 1000.0));
+            engine.update_engine_state(Point { x: 500.0, y: 500.0 });
+            engine.set_physical_velocity(Some(Vector { dx: 200.0, dy: 0.0 }));
+            engine.handle_touch(
+                Point { x: 500.0, y: 500.0 },
+                0.01,
+                Some(Vector { dx: 2.0, dy: 0.0 }),
+            );
+            assert_eq!(
+                engine.velocity(),
+                Vector {
+                    dx: 2000.0,
+                    dy: 0.0
+                }
+            );
+        }
+    }
+
+    // A one finger flick to the right at `speed` trackpad widths per second, then the lift-off
+    fn flick_frames(speed: f64) -> Vec<ContactFrame> {
+        let mut frames: Vec<ContactFrame> = (0..10)
+            .map(|index| touch_frame(1.0 + index as f64 * 0.01, 0.2 + index as f64 * 0.01 * speed))
+            .collect();
+        frames.push(ContactFrame {
+            timestamp: 1.1,
+            contacts: Vec::new(),
+        });
+        frames
+    }
+
+    #[test]
+    fn test_calibration_fits_glides() {
+        let _config = test_config(|_| {});
+        let bounds = rect(0.0, 0.0, 3000.0, 1000.0);
+        let release = Point { x: 200.0, y: 500.0 };
+        let mut truth = Config::defaults();
+        truth.set("trackpad_velocity_gain", "1.4").unwrap();
+        truth.set("glide_decay_per_second", "5.0").unwrap();
+        // Targets where the wanted settings would have stopped each flick
+        let trials: Vec<Trial> = [0.6, 1.0, 1.5, 2.2]
+            .into_iter()
+            .map(|speed| {
+                let mut trial = Trial {
+                    bounds,
+                    release,
+                    target: release,
+                    frames: flick_frames(speed),
+                };
+                trial.target = calibrate::simulate(&trial, &truth);
+                trial
+            })
+            .collect();
+        assert!(
+            trials[0].target.x > release.x + 100.0,
+            "{:?}",
+            trials[0].target
+        );
+        assert!(trials[3].target.x > trials[0].target.x);
+        assert_eq!(calibrate::miss_error(&trials, &truth), 0.0);
+
+        // The pointer does not take part in the replay, so neither may it in the fitted config
+        assert!(calibrate::fit(&trials, &Config::defaults()).is_err());
+        let mut base = Config::defaults();
+        base.velocity_blend = VelocityBlend::Trackpad;
+        let fit = calibrate::fit(&trials, &base).unwrap();
+        assert!(fit.error_before > 100.0, "{:?}", fit);
+        assert!(fit.error_after < fit.error_before / 5.0, "{:?}", fit);
+        assert_eq!(
+            calibrate::miss_error(&trials, &fit.apply(&base)),
+            fit.error_after
+        );
+        // Simulating never touches the live config
+        assert_eq!(
+            config().get("trackpad_velocity_gain"),
+            base.get("trackpad_velocity_gain")
+        );
+        let profile = fit.profile("fitted");
+        assert_eq!(profile.settings.len(), fit.values.len());
+        base.physical_velocity = true;
+        assert!(calibrate::fit(&trials, &base).is_err());
+        assert!(calibrate::fit(&[], &base).is_err());
+
+        let text = calibrate::format_trials(&trials);
+        let parsed = calibrate::parse_trials(text.as_bytes()).unwrap();
+        assert_eq!(parsed.len(), trials.len());
+        assert_eq!(parsed[1].frames.len(), trials[1].frames.len());
+        assert!((parsed[1].target.x - trials[1].target.x).abs() < 0.01);
+        assert_eq!(calibrate::format_trials(&parsed), text);
+        assert!(calibrate::parse_trials("1.0 0.5,0.5\n".as_bytes()).is_err());
+        assert!(calibrate::parse_trials("trial 0,0 1,1 2,2\n".as_bytes()).is_err());
+    }
+
+    #[test]
+    fn test_calibration_from_defaults() {
+        let _config = test_config(|_| {});
+        let defaults = Config::defaults();
+        assert!(calibrate::check_supported(&defaults).is_err());
+        // The session settings make a stock install calibratable
+        let session = calibrate::session_settings().apply(&defaults).unwrap();
+        assert!(calibrate::check_supported(&session).is_ok());
+        let mut trial = Trial {
+            bounds: rect(0.0, 0.0, 3000.0, 1000.0),
+            release: Point { x: 200.0, y: 500.0 },
+            target: Point { x: 200.0, y: 500.0 },
+            frames: flick_frames(1.0),
+        };
+        trial.target = calibrate::simulate(&trial, &session);
+        trial.target.x += 150.0;
+        let fit = calibrate::fit(&[trial], &session).unwrap();
+        // The saved profile leaves the defaults' own blend and curve alone
+        let fitted = fit.profile("fitted").apply(&defaults).unwrap();
+        for key in ["velocity_blend", "acceleration_curve", "physical_velocity"] {
+            assert_eq!(fitted.get(key), defaults.get(key), "{}", key);
+        }
+        assert_ne!(
+            fitted.get("trackpad_velocity_gain"),
+            defaults.get("trackpad_velocity_gain")
+        );
+    }
+
+    #[test]
+    fn test_calibration_session() {
+        let _config = test_config(|_| {});
+        let monitor = TrackpadMonitor::with_enumerator(Box::new(NoDevices));
+        assert!(monitor.take_captured(10.0).is_empty());
+        monitor.start_capture();
+        // A stray tap, then the flick
+        monitor.inject_frame(1, &touch_frame(0.5, 0.5));
+        monitor.inject_frame(
+            1,
+            &ContactFrame {
+                timestamp: 0.6,
+                contacts: Vec::new(),
+            },
+        );
+        for frame in flick_frames(1.0) {
+            monitor.inject_frame(1, &frame);
+        }
+        monitor.inject_frame(1, &touch_frame(2.0, 0.5));
+        let bounds = rect(0.0, 0.0, 1000.0, 1000.0);
+        let mut session = CalibrationSession::new(bounds);
+        let release = Point { x: 100.0, y: 100.0 };
+        // The flick glides, the correction after it does not
+        assert!(session.release(monitor.take_captured(1.1), release));
+        assert_eq!(monitor.take_captured(2.0).len(), 1);
+        assert!(!session.release(Vec::new(), Point { x: 400.0, y: 100.0 }));
+        assert_eq!(
+            session.trials(),
+            [Trial {
+                bounds,
+                release,
+                target: Point { x: 400.0, y: 100.0 },
+                frames: flick_frames(1.0),
+            }]
+        );
+        assert_eq!(
+            session.trials()[0].miss(Point { x: 350.0, y: 120.0 }),
+            -50.0
+        );
+        assert!(session.release(Vec::new(), release));
+        assert_eq!(session.into_trials().len(), 1);
+        monitor.stop_capture();
+    }
+
+    // A flick, then two fingers moving together and lifting right after
+    fn sweep_session() -> Vec<ContactFrame> {
+        let mut frames = flick_frames(1.5);
+        for index in 0..10 {
+            let x = 0.3 + index as f64 * 0.015;
+            frames.push(ContactFrame {
+                timestamp: 3.0 + index as f64 * 0.01,
+                contacts: vec![
+                    Contact::at(Point { x, y: 0.4 }),
+                    Contact::at(Point { x, y: 0.6 }),
+                ],
+            });
+        }
+        frames.push(ContactFrame {
+            timestamp: 3.1,
+            contacts: Vec::new(),
+        });
+        frames
+    }
+
+    #[test]
+    fn test_sweep() {
+        let _config = test_config(|_| {});
+        assert_eq!(
+            Axis::parse("glide_decay_per_second=4,6.5"),
+            Ok((
+                "glide_decay_per_second".to_string(),
+                Axis::Values(vec!["4".to_string(), "6.5".to_string()])
+            ))
+        );
+        assert_eq!(
+            Axis::parse("min_dt=0.002..0.01").unwrap().1,
+            Axis::Range(0.002, 0.01)
+        );
+        assert!(Axis::parse("speed=1,2").is_err());
+        assert!(Axis::parse("min_dt=0.01..0.002").is_err());
+        // Replays override or never read these
+        assert!(Axis::parse("velocity_blend=max").is_err());
+        assert!(Axis::parse("idle_delay=1").is_err());
+
+        let grid = [
+            Axis::parse("glide_decay_per_second=4,6.5").unwrap(),
+            Axis::parse("minimum_glide_velocity=100,200,300").unwrap(),
+        ];
+        let combinations = sweep::combinations(&grid, 100, 1);
+        assert_eq!(combinations.len(), 6);
+        assert_eq!(
+            combinations[5],
+            [
+                ("glide_decay_per_second".to_string(), "6.5".to_string()),
+                ("minimum_glide_velocity".to_string(), "300".to_string())
+            ]
+        );
+        let random = [
+            Axis::parse("glide_decay_per_second=4..8").unwrap(),
+            Axis::parse("snap_diagonals=true,false").unwrap(),
+        ];
+        let samples = sweep::combinations(&random, 20, 7);
+        assert_eq!(samples.len(), 20);
+        assert_eq!(samples, sweep::combinations(&random, 20, 7));
+        assert!(samples.iter().all(|sample| {
+            let decay: f64 = sample[0].1.parse().unwrap();
+            (4.0..=8.0).contains(&decay)
+        }));
+
+        let distribution = Distribution::of(vec![5.0, 1.0, 3.0, 2.0, 4.0]);
+        assert_eq!(
+            (
+                distribution.min,
+                distribution.p50,
+                distribution.p90,
+                distribution.max
+            ),
+            (1.0, 3.0, 5.0, 5.0)
+        );
+        assert_eq!(distribution.mean, 3.0);
+        assert_eq!(Distribution::of(Vec::new()), Distribution::default());
+
+        // The two finger release only glides without the suppression deadline
+        let bounds = rect(0.0, 0.0, 1440.0, 900.0);
+        let sessions = [sweep_session()];
+        let metrics = sweep::evaluate(&sessions, &Config::defaults(), bounds, None);
+        assert_eq!(
+            (metrics.releases, metrics.glides, metrics.spurious_glides),
+            (2, 1, 0)
+        );
+        assert!(metrics.glide_distance.mean > 50.0, "{:?}", metrics);
+        assert!(metrics.time_to_stop.mean > 0.05, "{:?}", metrics);
+        let mut unsuppressed = Config::defaults();
+        unsuppressed
+            .set("multi_finger_suppression_deadline", "0")
+            .unwrap();
+        let metrics = sweep::evaluate(&sessions, &unsuppressed, bounds, None);
+        assert_eq!((metrics.glides, metrics.spurious_glides), (2, 1));
+
+        let dir = std::env::temp_dir().join(format!("lapsus-sweep-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let trace = dir.join("session.trace");
+        let text: Vec<String> = sweep_session().iter().map(ContactFrame::to_line).collect();
+        std::fs::write(&trace, text.join("\n")).unwrap();
+        let args = |extra: &[&str]| {
+            let mut args: Vec<String> = [
+                "--vary",
+                "multi_finger_suppression_deadline=0,0.15",
+                "--vary",
+                "glide_press=off",
+            ]
+            .iter()
+            .chain(extra)
+            .map(|arg| arg.to_string())
+            .collect();
+            args.push(trace.display().to_string());
+            args
+        };
+        let csv = sweep::sweep(&args(&[])).unwrap();
+        // The defaults blend with the pointer, which replays do not have
+        assert_eq!(csv.overridden, ["velocity_blend = trackpad instead of max"]);
+        let csv = csv.report;
+        let lines: Vec<&str> = csv.lines().collect();
+        assert_eq!(lines.len(), 3);
+        assert!(lines[0].starts_with(
+            "multi_finger_suppression_deadline,glide_press,releases,glides,spurious_glides,"
+        ));
+        assert!(lines[1].starts_with("0,off,2,2,1,"), "{}", csv);
+        assert!(lines[2].starts_with("0.15,off,2,1,0,"), "{}", csv);
+        let json = sweep::sweep(&args(&["--format", "json"])).unwrap().report;
+        assert!(json.starts_with("[{\"settings\":{\"multi_finger_suppression_deadline\":\"0\""));
+        assert_eq!(json.matches("\"spurious_glides\"").count(), 2);
+        let output = dir.join("sweep.csv");
+        let written = sweep::sweep(&args(&["--output", output.to_str().unwrap()]))
+            .unwrap()
+            .report;
+        assert!(written.is_empty());
+        assert_eq!(std::fs::read_to_string(&output).unwrap(), csv);
+        assert!(sweep::sweep(&["--vary".to_string(), "min_dt=1".to_string()]).is_err());
+        assert!(sweep::sweep(&args(&["--format", "xml"])).is_err());
+        let physical = ["--vary", "physical_velocity=true,false"];
+        assert!(sweep::sweep(&args(&physical)).is_err());
+        let sized = [&physical[..], &["--device-size", "100x70"]].concat();
+        assert!(sweep::sweep(&args(&sized)).is_ok());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
 }
@@ -0,0 +1,72 @@
+// Touch traces: the raw contact frames of a session, one per line, for replay and offline tuning
+
+use crate::frame::{Contact, ContactFrame, format_line};
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+const HEADER: &str = "# lapsus trace v1";
+
+pub struct TraceRecorder {
+    path: PathBuf,
+    writer: BufWriter<File>,
+    frames: usize,
+}
+
+impl TraceRecorder {
+    pub fn create(path: &Path) -> io::Result<Self> {
+        let mut writer = BufWriter::new(File::create(path)?);
+        writeln!(writer, "{}", HEADER)?;
+        log::debug!("trace recording to {}", path.display());
+        Ok(Self {
+            path: path.to_path_buf(),
+            writer,
+            frames: 0,
+        })
+    }
+
+    pub fn record(&mut self, timestamp: f64, contacts: &[Contact]) {
+        if let Err(error) = writeln!(self.writer, "{}", format_line(timestamp, contacts)) {
+            log::warn!("trace write failed: {}", error);
+            return;
+        }
+        self.frames += 1;
+    }
+
+    pub fn frames(&self) -> usize {
+        self.frames
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn finish(mut self) -> io::Result<(PathBuf, usize)> {
+        self.writer.flush()?;
+        log::debug!("trace stopped after {} frames", self.frames);
+        Ok((self.path, self.frames))
+    }
+}
+
+pub fn read_trace(path: &Path) -> io::Result<Vec<ContactFrame>> {
+    parse_trace(BufReader::new(File::open(path)?))
+}
+
+pub fn parse_trace<R: BufRead>(reader: R) -> io::Result<Vec<ContactFrame>> {
+    let mut frames = Vec::new();
+    for (index, line) in reader.lines().enumerate() {
+        let line = line?;
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let frame = ContactFrame::parse_line(line).map_err(|error| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("line {}: {}", index + 1, error),
+            )
+        })?;
+        frames.push(frame);
+    }
+    Ok(frames)
+}
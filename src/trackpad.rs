@@ -1,187 +1,607 @@
 // warning: a lot of this is llm code, but some attempted optimizations have been made by me to make it a little better
 
+use crate::clock;
+use crate::device::{
+    DeviceChange, DeviceEnumerator, DeviceInfo, DeviceRegistry, DeviceRules, FrameCallback,
+};
+use crate::frame::{Contact, ContactFrame};
+use crate::geometry::{Float, Point, Vector};
+use crate::press::{self, PressTracker};
+use crate::scheduler::Waker;
+use crate::snapshot::{SnapshotReader, SnapshotWriter, snapshot_channel};
+use crate::spin;
+use crate::trace::TraceRecorder;
 use crate::{config, engine::ZERO_VECTOR};
-use cidre::cg::{Float, Point, Vector};
-use macos_multitouch::{self, MultitouchDevice};
+use std::collections::{HashMap, VecDeque};
+use std::io;
 use std::mem;
-use std::sync::{Arc, Mutex};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError, Sender, SyncSender};
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
 
 pub const ZERO_POINT: Point = Point { x: 0.0, y: 0.0 };
 
-pub struct TouchMetrics {
+// Replayed traces come from this pseudo device
+pub const REPLAY_DEVICE: u64 = 0;
+
+// Hot-plug notifications come in bursts, the devices are enumerated once they stop
+const DEVICE_SETTLE_TIME: Duration = Duration::from_millis(250);
+
+// Frames kept between two drains, about two seconds of input at the trackpad's frame rate
+pub const FRAME_QUEUE_CAPACITY: usize = 256;
+
+// Everything the main thread needs from the input callback, published once per frame
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TouchSnapshot {
+    // The active device, the one touched most recently. None before the first touch
+    pub device: Option<u64>,
+    pub is_touching: bool,
     pub centroid: Option<Point>,
+    // Fingers down in the newest frame
+    pub contacts: usize,
+    // Zero while not touching
     pub normalized_velocity: Vector,
-    pub is_touching: bool,
+    // How firmly the fingers pressed just before the newest frame, 0 to 1. Kept on lift-off
+    pub contact_weight: Float,
+    // In mm/s, with `physical_velocity` on and the size of the device known
+    pub physical_velocity: Option<Vector>,
+    pub angular_velocity: Float,
+    // Of the newest frame, lift-offs included. Zero before the first frame
+    pub timestamp: f64,
+    // Wall clock time until which a release does not glide, after a multi-finger gesture
+    pub suppress_glide_until: f64,
 }
 
-struct TrackpadState {
+impl TouchSnapshot {
+    pub const EMPTY: TouchSnapshot = TouchSnapshot {
+        device: None,
+        is_touching: false,
+        centroid: None,
+        contacts: 0,
+        normalized_velocity: ZERO_VECTOR,
+        contact_weight: 0.0,
+        physical_velocity: None,
+        angular_velocity: 0.0,
+        timestamp: 0.0,
+        suppress_glide_until: 0.0,
+    };
+
+    pub fn frame_timestamp(&self) -> Option<f64> {
+        (self.timestamp > 0.0).then_some(self.timestamp)
+    }
+
+    pub fn suppresses_glide(&self, now: f64) -> bool {
+        now < self.suppress_glide_until
+    }
+}
+
+// Touch tracking of one device, so fingers on two trackpads never mix into one centroid
+struct DeviceTouch {
     is_touching: bool,
-    latest_positions: Vec<Point>,
+    latest_contacts: Vec<Contact>,
     latest_centroid: Option<Point>,
     previous_centroid: Option<Point>,
     last_sample_timestamp: f64,
     normalized_velocity: Vector,
+    // Surface in millimetres, from the device rules or the device itself
+    size_mm: Option<(Float, Float)>,
+    physical_velocity: Option<Vector>,
+    press: PressTracker,
+    // Recent centroids within `spin_window`, oldest first
+    centroid_history: VecDeque<(f64, Point)>,
+    angular_velocity: Float,
+}
+
+impl DeviceTouch {
+    fn new(size_mm: Option<(Float, Float)>) -> Self {
+        Self {
+            is_touching: false,
+            latest_contacts: Vec::new(),
+            latest_centroid: None,
+            previous_centroid: None,
+            last_sample_timestamp: 0.0,
+            normalized_velocity: ZERO_VECTOR,
+            size_mm,
+            physical_velocity: None,
+            press: PressTracker::default(),
+            centroid_history: VecDeque::new(),
+            angular_velocity: 0.0,
+        }
+    }
+}
+
+struct TrackpadState {
+    devices: HashMap<u64, DeviceTouch>,
+    // Only the active device drives the cursor
+    active_device: Option<u64>,
     suppress_glide_deadline: f64,
+    recorder: Option<TraceRecorder>,
+    // Frames kept in memory for a calibration session
+    capture: Option<Vec<ContactFrame>>,
+    // Wakes an idle tick scheduler on the first frame of a touch
+    waker: Option<Arc<Waker>>,
+    snapshots: SnapshotWriter<TouchSnapshot>,
+    queue: SyncSender<TouchSnapshot>,
+    dropped_frames: Arc<AtomicU64>,
+}
+
+// The state behind the mutex belongs to the producers, the device callbacks and a replay. The
+// main thread only takes it for recording and setup, and reads touches through `snapshot`
+// `Stop` ends the thread, which hands the enumerator back
+type DeviceThread = (Sender<DeviceSignal>, JoinHandle<Box<dyn DeviceEnumerator>>);
+
+enum DeviceSignal {
+    Changed,
+    Stop,
 }
 
 pub struct TrackpadMonitor {
-    devices: Vec<MultitouchDevice>,
+    // Owned by the device thread while the monitor runs
+    enumerator: Option<Box<dyn DeviceEnumerator>>,
+    device_thread: Option<DeviceThread>,
+    devices: Arc<Mutex<Vec<DeviceInfo>>>,
+    device_rules: Arc<Mutex<DeviceRules>>,
+    device_channels: Arc<Mutex<Vec<Sender<DeviceChange>>>>,
     state: Arc<Mutex<TrackpadState>>,
-    listener_started: bool,
+    snapshots: SnapshotReader<TouchSnapshot>,
+    // Every frame in order, so touches shorter than a tick are still seen
+    queue: Receiver<TouchSnapshot>,
+    dropped_frames: Arc<AtomicU64>,
+    replaying: Arc<AtomicBool>,
 }
 
 impl TrackpadMonitor {
+    #[cfg(target_os = "macos")]
     pub fn new() -> Self {
+        Self::with_enumerator(Box::new(multitouch::MultitouchEnumerator))
+    }
+
+    pub fn with_enumerator(enumerator: Box<dyn DeviceEnumerator>) -> Self {
+        let (writer, snapshots) = snapshot_channel(TouchSnapshot::EMPTY);
+        let (sender, queue) = mpsc::sync_channel(FRAME_QUEUE_CAPACITY);
+        let dropped_frames = Arc::new(AtomicU64::new(0));
         Self {
-            devices: Vec::new(),
+            enumerator: Some(enumerator),
+            device_thread: None,
+            devices: Arc::new(Mutex::new(Vec::new())),
+            device_rules: Arc::new(Mutex::new(DeviceRules::default())),
+            device_channels: Arc::new(Mutex::new(Vec::new())),
             state: Arc::new(Mutex::new(TrackpadState {
-                is_touching: false,
-                latest_positions: Vec::new(),
-                latest_centroid: None,
-                previous_centroid: None,
-                last_sample_timestamp: 0.0,
-                normalized_velocity: Vector { dx: 0.0, dy: 0.0 },
+                devices: HashMap::new(),
+                active_device: None,
                 suppress_glide_deadline: 0.0,
+                recorder: None,
+                capture: None,
+                waker: None,
+                snapshots: writer,
+                queue: sender,
+                dropped_frames: dropped_frames.clone(),
             })),
-            listener_started: false,
+            snapshots,
+            queue,
+            dropped_frames,
+            replaying: Arc::new(AtomicBool::new(false)),
         }
     }
 
+    // Registers the connected devices, then enumerates them again on a background thread whenever
+    // the system reports a change, or every `device_poll_interval` seconds when it cannot
     pub fn start(&mut self) {
-        if self.listener_started {
+        let Some(mut enumerator) = self.enumerator.take() else {
             return;
-        }
-        self.listener_started = true;
-
+        };
         let state = self.state.clone();
-        let mut devices = macos_multitouch::get_multitouch_devices();
-        log::debug!("trackpad devices: {}", devices.len());
-        if devices.is_empty() {
-            log::warn!("no multitouch devices detected");
-        }
-
-        // Iterate over each multitouch device's data
-        for device in devices.iter_mut() {
-            let state = state.clone();
-            let _ = device.register_contact_frame_callback(
-                move |_device, data: &[macos_multitouch::Finger], timestamp, _frame| {
-                    let mut state = state.lock().expect("trackpad state lock poisoned");
-
-                    // Reuse the existing positions buffer
-                    let mut positions = mem::take(&mut state.latest_positions);
-                    positions.clear();
-                    positions.reserve(data.len());
-
-                    // Get the position of each finger and update the touch metrics
-                    for finger in data {
-                        positions.push(Point {
-                            x: finger.normalized.pos.x as Float,
-                            y: finger.normalized.pos.y as Float,
-                        });
+        let devices = self.devices.clone();
+        let rules = self.device_rules.clone();
+        let channels = self.device_channels.clone();
+        let (signal, signals) = mpsc::channel();
+        let notify = signal.clone();
+        let spawned = thread::Builder::new()
+            .name("lapsus-devices".to_string())
+            .spawn(move || {
+                let watch = enumerator.watch(Box::new(move || {
+                    let _ = notify.send(DeviceSignal::Changed);
+                }));
+                let mut registry = DeviceRegistry::default();
+                let mut first = true;
+                loop {
+                    let changes = registry.refresh(enumerator.as_mut(), |info| {
+                        connect_device(&state, &rules, info)
+                    });
+                    if first && registry.devices().is_empty() {
+                        log::warn!("no multitouch devices detected");
                     }
-                    update_touch_metrics(&mut state, &positions, timestamp);
-                    state.latest_positions = positions;
-                },
-            );
+                    first = false;
+                    publish_device_changes(&state, &devices, &channels, &registry, changes);
+                    // Woken by hot-plug notifications, polling only where there are none
+                    let interval = config().device_poll_interval;
+                    let next = if watch.is_none() && interval > 0.0 {
+                        signals.recv_timeout(Duration::from_secs_f64(interval))
+                    } else {
+                        signals.recv().map_err(|_| RecvTimeoutError::Disconnected)
+                    };
+                    match next {
+                        Ok(DeviceSignal::Changed) => {}
+                        Err(RecvTimeoutError::Timeout) => continue,
+                        Ok(DeviceSignal::Stop) | Err(RecvTimeoutError::Disconnected) => break,
+                    }
+                    let settled = loop {
+                        match signals.recv_timeout(DEVICE_SETTLE_TIME) {
+                            Ok(DeviceSignal::Changed) => {}
+                            Err(RecvTimeoutError::Timeout) => break true,
+                            Ok(DeviceSignal::Stop) | Err(RecvTimeoutError::Disconnected) => {
+                                break false;
+                            }
+                        }
+                    };
+                    if !settled {
+                        break;
+                    }
+                }
+                drop(watch);
+                let changes = registry.stop_all();
+                publish_device_changes(&state, &devices, &channels, &registry, changes);
+                enumerator
+            });
+        match spawned {
+            Ok(handle) => self.device_thread = Some((signal, handle)),
+            Err(error) => log::warn!("device thread failed to start: {}", error),
         }
-
-        self.devices = devices;
     }
 
     pub fn stop(&mut self) {
-        for device in self.devices.iter_mut() {
-            device.stop();
+        let Some((signal, handle)) = self.device_thread.take() else {
+            return;
+        };
+        let _ = signal.send(DeviceSignal::Stop);
+        match handle.join() {
+            Ok(enumerator) => self.enumerator = Some(enumerator),
+            Err(_) => log::warn!("device thread panicked"),
         }
-        self.devices.clear();
-        self.listener_started = false;
     }
 
-    pub fn is_touching(&self) -> bool {
-        self.state
+    // Consulted for the size of every device connected from now on
+    pub fn set_device_rules(&self, rules: DeviceRules) {
+        *self
+            .device_rules
             .lock()
-            .expect("trackpad state lock poisoned")
-            .is_touching
+            .expect("device rules lock poisoned") = rules;
     }
 
-    pub fn current_touch_positions(&self) -> Vec<Point> {
-        self.state
+    pub fn devices(&self) -> Vec<DeviceInfo> {
+        self.devices
             .lock()
-            .expect("trackpad state lock poisoned")
-            .latest_positions
+            .expect("device list lock poisoned")
             .clone()
     }
 
-    pub fn current_touch_centroid(&self) -> Option<Point> {
-        self.state
+    pub fn device(&self, id: u64) -> Option<DeviceInfo> {
+        self.devices
             .lock()
-            .expect("trackpad state lock poisoned")
-            .latest_centroid
+            .expect("device list lock poisoned")
+            .iter()
+            .find(|info| info.id == id)
+            .cloned()
     }
 
-    pub fn current_normalized_velocity(&self) -> Option<Vector> {
-        let state = self.state.lock().expect("trackpad state lock poisoned");
-        if state.is_touching {
-            Some(state.normalized_velocity)
-        } else {
-            None
+    // Devices added and removed from now on, including the ones found at start
+    pub fn device_events(&self) -> Receiver<DeviceChange> {
+        let (sender, receiver) = mpsc::channel();
+        self.device_channels
+            .lock()
+            .expect("device channel lock poisoned")
+            .push(sender);
+        receiver
+    }
+
+    // The touch state as of the newest frame, read without blocking the input callback
+    pub fn snapshot(&mut self) -> TouchSnapshot {
+        self.snapshots.read()
+    }
+
+    // Replaces `frames` with every frame since the last drain, oldest first. The last entry is
+    // always the current state, even when the queue overflowed or nothing new came in
+    pub fn drain_frames(&mut self, frames: &mut Vec<TouchSnapshot>) {
+        frames.clear();
+        frames.extend(self.queue.try_iter());
+        let latest = self.snapshots.read();
+        if frames
+            .last()
+            .is_none_or(|frame| frame.timestamp < latest.timestamp)
+        {
+            frames.push(latest);
         }
     }
 
-    pub fn metrics(&self) -> TouchMetrics {
-        let state = self.state.lock().expect("trackpad state lock poisoned");
-        TouchMetrics {
-            centroid: state.latest_centroid,
-            normalized_velocity: state.normalized_velocity,
-            is_touching: state.is_touching,
+    // Frames lost because the queue was full, since the monitor was created
+    pub fn dropped_frames(&self) -> u64 {
+        self.dropped_frames.load(Ordering::Relaxed)
+    }
+
+    pub fn start_recording(&self, path: &Path) -> io::Result<()> {
+        let recorder = TraceRecorder::create(path)?;
+        let previous = lock_state(&self.state).recorder.replace(recorder);
+        if let Some(previous) = previous {
+            previous.finish()?;
         }
+        Ok(())
     }
 
-    // Flag to prevent erroneous gliding when there is more than one finger touching the trackpad
-    pub fn should_suppress_glide(&self) -> bool {
-        let deadline = self
-            .state
-            .lock()
-            .expect("trackpad state lock poisoned")
-            .suppress_glide_deadline;
-        objc2_core_foundation::CFAbsoluteTimeGetCurrent() < deadline
+    // Returns the trace path and the number of frames written, or None if nothing was recording
+    pub fn stop_recording(&self) -> io::Result<Option<(PathBuf, usize)>> {
+        let recorder = lock_state(&self.state).recorder.take();
+        recorder.map(TraceRecorder::finish).transpose()
+    }
+
+    pub fn start_capture(&self) {
+        lock_state(&self.state).capture = Some(Vec::new());
+    }
+
+    pub fn stop_capture(&self) {
+        lock_state(&self.state).capture = None;
+    }
+
+    // Captured frames up to and including `timestamp`, newer ones stay for the next call
+    pub fn take_captured(&self, timestamp: f64) -> Vec<ContactFrame> {
+        let mut state = lock_state(&self.state);
+        let Some(capture) = state.capture.as_mut() else {
+            return Vec::new();
+        };
+        let count = capture.partition_point(|frame| frame.timestamp <= timestamp);
+        capture.drain(..count).collect()
+    }
+
+    pub fn set_waker(&self, waker: Arc<Waker>) {
+        lock_state(&self.state).waker = Some(waker);
+    }
+
+    pub fn is_recording(&self) -> bool {
+        lock_state(&self.state).recorder.is_some()
+    }
+
+    // Injected frames of `device` are measured on a surface of this size, in millimetres
+    pub fn set_device_size(&self, device: u64, size_mm: Option<(Float, Float)>) {
+        lock_state(&self.state)
+            .devices
+            .insert(device, DeviceTouch::new(size_mm));
+    }
+
+    // One frame as if `device` had just reported it
+    pub fn inject_frame(&self, device: u64, frame: &ContactFrame) {
+        let mut state = lock_state(&self.state);
+        ingest_frame(
+            &mut state,
+            device,
+            frame.contacts.iter().copied(),
+            frame.timestamp,
+        );
+    }
+
+    // Feeds recorded frames through the same path as the device callback, paced by their timestamps
+    pub fn replay(&self, mut frames: Vec<ContactFrame>) -> io::Result<()> {
+        if self.replaying.swap(true, Ordering::AcqRel) {
+            return Err(io::Error::new(
+                io::ErrorKind::WouldBlock,
+                "a replay is already running",
+            ));
+        }
+        // Always finish with the fingers lifted so the replay can end in a glide
+        if frames
+            .last()
+            .is_some_and(|frame| !frame.contacts.is_empty())
+        {
+            let timestamp = frames[frames.len() - 1].timestamp + config().min_dt;
+            frames.push(ContactFrame {
+                timestamp,
+                contacts: Vec::new(),
+            });
+        }
+        let state = self.state.clone();
+        let replaying = self.replaying.clone();
+        let spawned = thread::Builder::new()
+            .name("lapsus-replay".to_string())
+            .spawn(move || {
+                let mut previous_timestamp = frames.first().map(|frame| frame.timestamp);
+                for frame in &frames {
+                    if let Some(previous) = previous_timestamp {
+                        let wait = (frame.timestamp - previous).max(0.0);
+                        thread::sleep(Duration::from_secs_f64(wait));
+                    }
+                    previous_timestamp = Some(frame.timestamp);
+                    let mut state = lock_state(&state);
+                    ingest_frame(
+                        &mut state,
+                        REPLAY_DEVICE,
+                        frame.contacts.iter().copied(),
+                        frame.timestamp,
+                    );
+                }
+                log::debug!("replay finished after {} frames", frames.len());
+                replaying.store(false, Ordering::Release);
+            });
+        if let Err(error) = spawned {
+            self.replaying.store(false, Ordering::Release);
+            return Err(error);
+        }
+        Ok(())
     }
 }
 
-fn update_touch_metrics(state: &mut TrackpadState, positions: &[Point], timestamp: f64) {
-    let config = config();
-    if positions.len() > 1 {
-        let now = objc2_core_foundation::CFAbsoluteTimeGetCurrent();
-        state.suppress_glide_deadline = now + config.multi_finger_suppression_deadline;
+fn ingest_frame(
+    state: &mut TrackpadState,
+    device: u64,
+    contacts: impl Iterator<Item = Contact>,
+    timestamp: f64,
+) {
+    let touch = state
+        .devices
+        .entry(device)
+        .or_insert_with(|| DeviceTouch::new(None));
+    // Reuse the existing contacts buffer
+    let mut buffer = mem::take(&mut touch.latest_contacts);
+    buffer.clear();
+    buffer.extend(contacts);
+    let was_touching = touch.is_touching;
+    update_touch_metrics(touch, &buffer, timestamp);
+    // The device touched last takes over, frames from the others only keep their own state current
+    if touch.is_touching && !was_touching && state.active_device != Some(device) {
+        log::debug!("active trackpad {:#x}", device);
+        state.active_device = Some(device);
+    }
+    if state.active_device != Some(device) {
+        touch.latest_contacts = buffer;
+        return;
+    }
+    if buffer.len() > 1 {
+        let now = clock::wall_time();
+        state.suppress_glide_deadline = now + config().multi_finger_suppression_deadline;
+    }
+    if let Some(recorder) = state.recorder.as_mut() {
+        recorder.record(timestamp, &buffer);
+    }
+    if let Some(capture) = state.capture.as_mut() {
+        capture.push(ContactFrame {
+            timestamp,
+            contacts: buffer.clone(),
+        });
     }
-    let was_touching = state.is_touching;
-    state.is_touching = !positions.is_empty();
-    if state.is_touching != was_touching {
-        log::debug!("touch {}", if state.is_touching { "start" } else { "end" });
+    touch.latest_contacts = buffer;
+    let snapshot = TouchSnapshot {
+        device: Some(device),
+        is_touching: touch.is_touching,
+        centroid: touch.latest_centroid,
+        contacts: touch.latest_contacts.len(),
+        normalized_velocity: touch.normalized_velocity,
+        contact_weight: touch.press.weight(),
+        physical_velocity: touch.physical_velocity,
+        angular_velocity: touch.angular_velocity,
+        timestamp: touch.last_sample_timestamp,
+        suppress_glide_until: state.suppress_glide_deadline,
+    };
+    state.snapshots.publish(snapshot);
+    if state.queue.try_send(snapshot).is_err()
+        && state.dropped_frames.fetch_add(1, Ordering::Relaxed) == 0
+    {
+        log::warn!("touch frame queue full, dropping frames");
+    }
+    if let Some(waker) = state.waker.as_ref() {
+        waker.wake();
+    }
+}
+
+// Every frame goes through this lock, so a panic while holding it must not stop tracking for good.
+// The worst a panic leaves behind is one half-ingested frame, which the next frames overwrite
+fn lock_state(state: &Mutex<TrackpadState>) -> MutexGuard<'_, TrackpadState> {
+    state.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+// Sets up the touch tracking of a new device and returns the callback feeding it
+fn connect_device(
+    state: &Arc<Mutex<TrackpadState>>,
+    rules: &Mutex<DeviceRules>,
+    info: &DeviceInfo,
+) -> FrameCallback {
+    let size_mm = rules
+        .lock()
+        .expect("device rules lock poisoned")
+        .settings_for(info)
+        .size_mm
+        .or(info.size_mm());
+    if size_mm.is_none() {
+        log::debug!("size of trackpad {:#x} unknown", info.id);
+    }
+    lock_state(state)
+        .devices
+        .insert(info.id, DeviceTouch::new(size_mm));
+    let state = state.clone();
+    let device = info.id;
+    Box::new(move |contacts, timestamp| {
+        let mut state = lock_state(&state);
+        ingest_frame(&mut state, device, contacts, timestamp);
+    })
+}
+
+fn publish_device_changes(
+    state: &Mutex<TrackpadState>,
+    devices: &Mutex<Vec<DeviceInfo>>,
+    channels: &Mutex<Vec<Sender<DeviceChange>>>,
+    registry: &DeviceRegistry,
+    changes: Vec<DeviceChange>,
+) {
+    if changes.is_empty() {
+        return;
+    }
+    *devices.lock().expect("device list lock poisoned") = registry.devices();
+    let mut channels = channels.lock().expect("device channel lock poisoned");
+    for change in changes {
+        log::info!("{}", change);
+        if let DeviceChange::Removed(info) = &change {
+            let mut state = lock_state(state);
+            release_device(&mut state, info.id);
+        }
+        channels.retain(|channel| channel.send(change.clone()).is_ok());
+    }
+}
+
+// A device unplugged mid-touch lifts its fingers, so the cursor is not left touching forever
+fn release_device(state: &mut TrackpadState, device: u64) {
+    let Some(touch) = state.devices.get(&device) else {
+        return;
+    };
+    if touch.is_touching {
+        let timestamp = touch.last_sample_timestamp + config().min_dt;
+        ingest_frame(state, device, &mut std::iter::empty(), timestamp);
+    }
+    state.devices.remove(&device);
+    if state.active_device == Some(device) {
+        state.active_device = None;
+    }
+}
+
+fn update_touch_metrics(touch: &mut DeviceTouch, contacts: &[Contact], timestamp: f64) {
+    let config = config();
+    let was_touching = touch.is_touching;
+    touch.is_touching = !contacts.is_empty();
+    if touch.is_touching != was_touching {
+        log::debug!("touch {}", if touch.is_touching { "start" } else { "end" });
     }
 
-    if positions.is_empty() {
-        state.latest_centroid = None;
-        state.previous_centroid = None;
-        state.normalized_velocity = ZERO_VECTOR;
-        state.last_sample_timestamp = timestamp;
+    if contacts.is_empty() {
+        touch.latest_centroid = None;
+        touch.previous_centroid = None;
+        touch.normalized_velocity = ZERO_VECTOR;
+        touch.physical_velocity = None;
+        touch.centroid_history.clear();
+        touch.angular_velocity = 0.0;
+        touch.last_sample_timestamp = timestamp;
         return;
     }
 
     // Find the average position of all the current touch points
     let mut centroid = ZERO_POINT;
-    for point in positions {
-        centroid.x += point.x;
-        centroid.y += point.y;
+    for contact in contacts {
+        centroid.x += contact.position.x;
+        centroid.y += contact.position.y;
     }
-    let divisor = positions.len() as Float;
+    let divisor = contacts.len() as Float;
     centroid.x /= divisor;
     centroid.y /= divisor;
-    state.latest_centroid = Some(centroid);
+    touch.latest_centroid = Some(centroid);
+
+    if !was_touching {
+        touch.press.reset();
+    }
+    let weight =
+        press::contact_weight(contacts, config.full_press_size, config.full_press_pressure);
+    touch.press.update(timestamp, weight, config.press_window);
 
     // Determine the velocity given the previous average if it exists
-    if let Some(previous) = state.previous_centroid {
-        if state.last_sample_timestamp > 0.0 {
-            let mut delta_time = (timestamp - state.last_sample_timestamp) as Float;
+    if let Some(previous) = touch.previous_centroid {
+        if touch.last_sample_timestamp > 0.0 {
+            let mut delta_time = (timestamp - touch.last_sample_timestamp) as Float;
             if delta_time < config.min_dt {
                 delta_time = config.min_dt;
             }
@@ -190,19 +610,234 @@ fn update_touch_metrics(state: &mut TrackpadState, positions: &[Point], timestam
                 dy: (centroid.y - previous.y) / delta_time,
             };
             // Apply velocity smoothing
-            state.normalized_velocity = Vector {
-                dx: state.normalized_velocity.dx * (1.0 - config.velocity_smoothing)
+            touch.normalized_velocity = Vector {
+                dx: touch.normalized_velocity.dx * (1.0 - config.velocity_smoothing)
                     + raw_velocity.dx * config.velocity_smoothing,
-                dy: state.normalized_velocity.dy * (1.0 - config.velocity_smoothing)
+                dy: touch.normalized_velocity.dy * (1.0 - config.velocity_smoothing)
                     + raw_velocity.dy * config.velocity_smoothing,
             };
         } else {
-            state.normalized_velocity = ZERO_VECTOR;
+            touch.normalized_velocity = ZERO_VECTOR;
         }
     } else {
-        state.normalized_velocity = ZERO_VECTOR;
+        touch.normalized_velocity = ZERO_VECTOR;
+    }
+    // The same hand motion gives the same mm/s on any trackpad
+    touch.physical_velocity =
+        touch
+            .size_mm
+            .filter(|_| config.physical_velocity)
+            .map(|(width, height)| Vector {
+                dx: touch.normalized_velocity.dx * width,
+                dy: touch.normalized_velocity.dy * height,
+            });
+
+    if config.curved_glides {
+        touch.centroid_history.push_back((timestamp, centroid));
+        while touch
+            .centroid_history
+            .front()
+            .is_some_and(|(time, _)| *time < timestamp - config.spin_window)
+        {
+            touch.centroid_history.pop_front();
+        }
+        touch.angular_velocity =
+            spin::estimate_angular_velocity(touch.centroid_history.make_contiguous());
+    }
+
+    touch.previous_centroid = Some(centroid);
+    touch.last_sample_timestamp = timestamp;
+}
+
+// The trackpads macOS reports through the private MultitouchSupport framework
+#[cfg(target_os = "macos")]
+mod multitouch {
+    use crate::device::{DeviceEnumerator, DeviceInfo, DeviceWatch, FrameCallback, TouchDevice};
+    use crate::frame::Contact;
+    use crate::geometry::{Float, Point};
+    use macos_multitouch::{self, MultitouchDevice};
+    use std::ffi::{c_char, c_void};
+    use std::ptr;
+
+    type IONotificationPortRef = *mut c_void;
+    type IOIterator = u32;
+    type DispatchQueue = *mut c_void;
+    type ChangeCallback = Box<dyn Fn() + Send + Sync>;
+
+    const KERN_SUCCESS: i32 = 0;
+    const KERN_FAILURE: i32 = 5;
+
+    #[link(name = "IOKit", kind = "framework")]
+    unsafe extern "C" {
+        fn IONotificationPortCreate(main_port: u32) -> IONotificationPortRef;
+        fn IONotificationPortDestroy(port: IONotificationPortRef);
+        fn IONotificationPortSetDispatchQueue(port: IONotificationPortRef, queue: DispatchQueue);
+        fn IOServiceMatching(name: *const c_char) -> *const c_void;
+        fn IOServiceAddMatchingNotification(
+            port: IONotificationPortRef,
+            notification_type: *const c_char,
+            matching: *const c_void,
+            callback: extern "C" fn(*mut c_void, IOIterator),
+            refcon: *mut c_void,
+            iterator: *mut IOIterator,
+        ) -> i32;
+        fn IOIteratorNext(iterator: IOIterator) -> u32;
+        fn IOObjectRelease(object: u32) -> i32;
+    }
+
+    unsafe extern "C" {
+        fn dispatch_queue_create(label: *const c_char, attributes: *const c_void) -> DispatchQueue;
+        fn dispatch_sync_f(
+            queue: DispatchQueue,
+            context: *mut c_void,
+            work: extern "C" fn(*mut c_void),
+        );
+        fn dispatch_release(object: DispatchQueue);
+    }
+
+    pub struct MultitouchEnumerator;
+
+    impl DeviceEnumerator for MultitouchEnumerator {
+        fn enumerate(&mut self) -> Vec<Box<dyn TouchDevice>> {
+            macos_multitouch::get_multitouch_devices()
+                .into_iter()
+                .map(|device| Box::new(device) as Box<dyn TouchDevice>)
+                .collect()
+        }
+
+        fn watch(&mut self, on_change: Box<dyn Fn() + Send + Sync>) -> Option<DeviceWatch> {
+            match HotplugWatch::new(on_change) {
+                Ok(watch) => Some(Box::new(watch)),
+                Err(code) => {
+                    log::warn!("trackpad notifications unavailable ({:#x}), polling", code);
+                    None
+                }
+            }
+        }
+    }
+
+    // IOKit notifications for multitouch devices matched or terminated, delivered on a private
+    // dispatch queue
+    struct HotplugWatch {
+        port: IONotificationPortRef,
+        queue: DispatchQueue,
+        iterators: [IOIterator; 2],
+        on_change: *mut ChangeCallback,
+    }
+
+    // Only the queue calls back into `on_change`, which is Send and Sync itself
+    unsafe impl Send for HotplugWatch {}
+
+    impl HotplugWatch {
+        fn new(on_change: ChangeCallback) -> Result<Self, i32> {
+            unsafe {
+                // The null port stands for the default IOKit main port
+                let port = IONotificationPortCreate(0);
+                if port.is_null() {
+                    return Err(KERN_FAILURE);
+                }
+                let queue = dispatch_queue_create(c"lapsus.devices".as_ptr(), ptr::null());
+                IONotificationPortSetDispatchQueue(port, queue);
+                let mut watch = HotplugWatch {
+                    port,
+                    queue,
+                    iterators: [0; 2],
+                    on_change: Box::into_raw(Box::new(on_change)),
+                };
+                let notifications = [c"IOServiceFirstMatch", c"IOServiceTerminate"];
+                for (iterator, notification) in watch.iterators.iter_mut().zip(notifications) {
+                    // The matching dictionary is consumed by the call
+                    let result = IOServiceAddMatchingNotification(
+                        port,
+                        notification.as_ptr(),
+                        IOServiceMatching(c"AppleMultitouchDevice".as_ptr()),
+                        device_changed,
+                        watch.on_change.cast(),
+                        iterator,
+                    );
+                    if result != KERN_SUCCESS {
+                        return Err(result);
+                    }
+                    // Emptying the iterator arms it, the devices already there get enumerated
+                    // anyway
+                    drain(*iterator);
+                }
+                Ok(watch)
+            }
+        }
+    }
+
+    impl Drop for HotplugWatch {
+        fn drop(&mut self) {
+            unsafe {
+                for iterator in self.iterators {
+                    if iterator != 0 {
+                        IOObjectRelease(iterator);
+                    }
+                }
+                IONotificationPortDestroy(self.port);
+                // Waits out a callback still running on the queue before freeing its closure
+                dispatch_sync_f(self.queue, ptr::null_mut(), finished);
+                dispatch_release(self.queue);
+                drop(Box::from_raw(self.on_change));
+            }
+        }
+    }
+
+    extern "C" fn device_changed(refcon: *mut c_void, iterator: IOIterator) {
+        unsafe {
+            drain(iterator);
+            (*refcon.cast::<ChangeCallback>())();
+        }
+    }
+
+    extern "C" fn finished(_context: *mut c_void) {}
+
+    unsafe fn drain(iterator: IOIterator) {
+        loop {
+            let service = unsafe { IOIteratorNext(iterator) };
+            if service == 0 {
+                break;
+            }
+            unsafe { IOObjectRelease(service) };
+        }
     }
 
-    state.previous_centroid = Some(centroid);
-    state.last_sample_timestamp = timestamp;
+    impl TouchDevice for MultitouchDevice {
+        fn info(&self) -> DeviceInfo {
+            // Reported in hundredths of a millimetre
+            let (width, height) = self.get_sensor_surface_dimensions();
+            DeviceInfo {
+                id: self.get_device_id(),
+                family: self.get_family_id(),
+                builtin: self.is_builtin(),
+                width_mm: width as Float / 100.0,
+                height_mm: height as Float / 100.0,
+            }
+        }
+
+        fn start(&mut self, on_frame: FrameCallback) {
+            let registered = self.register_contact_frame_callback(
+                move |_device, data: &[macos_multitouch::Finger], timestamp, _frame| {
+                    // Get the position, size and pressure of each finger
+                    let mut contacts = data.iter().map(|finger| Contact {
+                        position: Point {
+                            x: finger.normalized.pos.x as Float,
+                            y: finger.normalized.pos.y as Float,
+                        },
+                        size: finger.size as Float,
+                        pressure: finger.pressure as Float,
+                    });
+                    on_frame(&mut contacts, timestamp);
+                },
+            );
+            if let Err(error) = registered {
+                log::warn!("trackpad callback not registered: {}", error);
+            }
+        }
+
+        fn stop(&mut self) {
+            MultitouchDevice::stop(self);
+        }
+    }
 }
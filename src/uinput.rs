@@ -0,0 +1,169 @@
+// Linux counterpart to the CoreGraphics warp: a virtual relative pointer driven through uinput
+
+use crate::cursor::CursorSink;
+use crate::geometry::{Point, Vector};
+use std::io::{self, Write};
+
+pub const EV_SYN: u16 = 0x00;
+pub const EV_KEY: u16 = 0x01;
+pub const EV_REL: u16 = 0x02;
+pub const SYN_REPORT: u16 = 0x00;
+pub const REL_X: u16 = 0x00;
+pub const REL_Y: u16 = 0x01;
+pub const BTN_LEFT: u16 = 0x110;
+
+// struct timeval is two C longs, which are pointer sized on Linux
+const TIMEVAL_SIZE: usize = 2 * std::mem::size_of::<usize>();
+pub const INPUT_EVENT_SIZE: usize = TIMEVAL_SIZE + 2 + 2 + 4;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct InputEvent {
+    pub kind: u16,
+    pub code: u16,
+    pub value: i32,
+}
+
+impl InputEvent {
+    pub fn new(kind: u16, code: u16, value: i32) -> Self {
+        Self { kind, code, value }
+    }
+
+    // Layout of struct input_event, the kernel fills in the timestamp when it is zero
+    pub fn encode(&self, buffer: &mut Vec<u8>) {
+        buffer.extend_from_slice(&[0u8; TIMEVAL_SIZE]);
+        buffer.extend_from_slice(&self.kind.to_ne_bytes());
+        buffer.extend_from_slice(&self.code.to_ne_bytes());
+        buffer.extend_from_slice(&self.value.to_ne_bytes());
+    }
+
+    pub fn decode(bytes: &[u8]) -> Option<Self> {
+        if bytes.len() < INPUT_EVENT_SIZE {
+            return None;
+        }
+        let fields = &bytes[TIMEVAL_SIZE..INPUT_EVENT_SIZE];
+        Some(Self {
+            kind: u16::from_ne_bytes([fields[0], fields[1]]),
+            code: u16::from_ne_bytes([fields[2], fields[3]]),
+            value: i32::from_ne_bytes([fields[4], fields[5], fields[6], fields[7]]),
+        })
+    }
+}
+
+pub fn decode_events(bytes: &[u8]) -> Vec<InputEvent> {
+    bytes
+        .chunks_exact(INPUT_EVENT_SIZE)
+        .filter_map(InputEvent::decode)
+        .collect()
+}
+
+// Writes REL_X/REL_Y reports to any writer: the uinput node in production, a file or pipe in tests
+pub struct UinputCursorSink<W: Write> {
+    writer: W,
+    buffer: Vec<u8>,
+}
+
+impl<W: Write> UinputCursorSink<W> {
+    pub fn new(writer: W) -> Self {
+        Self {
+            writer,
+            buffer: Vec::with_capacity(3 * INPUT_EVENT_SIZE),
+        }
+    }
+
+    pub fn into_inner(self) -> W {
+        self.writer
+    }
+
+    // The engine already quantizes its steps to whole pixels. Engine deltas are y up while evdev
+    // relative motion is y down
+    pub fn emit_motion(&mut self, delta: Vector) -> io::Result<()> {
+        let step_x = delta.dx.round() as i32;
+        let step_y = delta.dy.round() as i32;
+        if step_x == 0 && step_y == 0 {
+            return Ok(());
+        }
+        self.buffer.clear();
+        if step_x != 0 {
+            InputEvent::new(EV_REL, REL_X, step_x).encode(&mut self.buffer);
+        }
+        if step_y != 0 {
+            InputEvent::new(EV_REL, REL_Y, -step_y).encode(&mut self.buffer);
+        }
+        InputEvent::new(EV_SYN, SYN_REPORT, 0).encode(&mut self.buffer);
+        self.writer.write_all(&self.buffer)?;
+        self.writer.flush()
+    }
+}
+
+impl<W: Write> CursorSink for UinputCursorSink<W> {
+    fn move_cursor(&mut self, _position: Point, delta: Vector) {
+        if let Err(error) = self.emit_motion(delta) {
+            log::warn!("uinput write failed: {}", error);
+        }
+    }
+}
+
+#[cfg(target_os = "linux")]
+mod device {
+    use super::{BTN_LEFT, EV_KEY, EV_REL, REL_X, REL_Y, UinputCursorSink};
+    use std::fs::{File, OpenOptions};
+    use std::io::{self, Write};
+    use std::os::fd::AsRawFd;
+
+    const UINPUT_PATH: &str = "/dev/uinput";
+    const UINPUT_MAX_NAME_SIZE: usize = 80;
+    const ABS_CNT: usize = 64;
+    const BUS_VIRTUAL: u16 = 0x06;
+
+    // _IO('U', 1), _IO('U', 2) and _IOW('U', 100..102, int)
+    const UI_DEV_CREATE: u64 = 0x5501;
+    const UI_DEV_DESTROY: u64 = 0x5502;
+    const UI_SET_EVBIT: u64 = 0x4004_5564;
+    const UI_SET_KEYBIT: u64 = 0x4004_5565;
+    const UI_SET_RELBIT: u64 = 0x4004_5566;
+
+    fn ioctl(file: &File, request: u64, value: i32) -> io::Result<()> {
+        let result = unsafe { libc::ioctl(file.as_raw_fd(), request as _, value) };
+        if result < 0 {
+            Err(io::Error::last_os_error())
+        } else {
+            Ok(())
+        }
+    }
+
+    // Legacy struct uinput_user_dev, still accepted by every kernel that has uinput
+    fn user_dev(name: &str) -> Vec<u8> {
+        let mut buffer = Vec::with_capacity(UINPUT_MAX_NAME_SIZE + 12 + 4 * 4 * ABS_CNT);
+        let mut name_bytes = [0u8; UINPUT_MAX_NAME_SIZE];
+        let length = name.len().min(UINPUT_MAX_NAME_SIZE - 1);
+        name_bytes[..length].copy_from_slice(&name.as_bytes()[..length]);
+        buffer.extend_from_slice(&name_bytes);
+        for id in [BUS_VIRTUAL, 0x1209, 0x1a95, 1] {
+            buffer.extend_from_slice(&id.to_ne_bytes());
+        }
+        buffer.extend_from_slice(&0u32.to_ne_bytes());
+        buffer.resize(buffer.len() + 4 * 4 * ABS_CNT, 0);
+        buffer
+    }
+
+    impl UinputCursorSink<File> {
+        pub fn open() -> io::Result<Self> {
+            let mut file = OpenOptions::new().write(true).open(UINPUT_PATH)?;
+            ioctl(&file, UI_SET_EVBIT, EV_REL as i32)?;
+            ioctl(&file, UI_SET_RELBIT, REL_X as i32)?;
+            ioctl(&file, UI_SET_RELBIT, REL_Y as i32)?;
+            // Without a button the device is not classified as a pointer
+            ioctl(&file, UI_SET_EVBIT, EV_KEY as i32)?;
+            ioctl(&file, UI_SET_KEYBIT, BTN_LEFT as i32)?;
+            file.write_all(&user_dev("Lapsus virtual pointer"))?;
+            ioctl(&file, UI_DEV_CREATE, 0)?;
+            log::debug!("uinput device created");
+            Ok(Self::new(file))
+        }
+
+        pub fn close(self) -> io::Result<()> {
+            let file = self.into_inner();
+            ioctl(&file, UI_DEV_DESTROY, 0)
+        }
+    }
+}
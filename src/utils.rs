@@ -1,4 +1,4 @@
-use cidre::cg::{Point, Rect, Size, EventSrc, EventSrcStateId};
+use crate::geometry::{Point, Rect, Size};
 
 pub fn min(a: f64, b: f64) -> f64 {
     if a > b { b } else { a }
@@ -29,7 +29,9 @@ pub fn union_rect(a: &Rect, b: &Rect) -> Rect {
     }
 }
 
+#[cfg(target_os = "macos")]
 pub fn disable_local_event_suppression() {
+    use cidre::cg::{EventSrc, EventSrcStateId};
     let state_id = EventSrcStateId::CombinedSession;
     let mut event_source_ref = EventSrc::with_state(state_id);
     if let Some(ref mut retained) = event_source_ref {